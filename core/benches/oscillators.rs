@@ -0,0 +1,54 @@
+#![feature(test)]
+extern crate test;
+
+use core::osc::Osc;
+use core::sine_bank::{BankFrame, SineBank, BANK_SIZE};
+use test::Bencher;
+
+const BLOCK: usize = 512;
+const SAMPLE_RATE: f32 = 48000.0;
+
+fn frequency(index: usize, sample: usize) -> f32 {
+    100.0 + 700.0 * index as f32 + sample as f32 * 0.01
+}
+
+#[bench]
+fn bench_osc(b: &mut Bencher) {
+    let mut oscs: Vec<Osc> = (0..BANK_SIZE).map(|_| Osc::new(0.0, 0.0, 0.0)).collect();
+    let (mut left, mut right) = ([0_f32; BLOCK], [0_f32; BLOCK]);
+    b.iter(|| {
+        for (index, osc) in oscs.iter_mut().enumerate() {
+            for (sample, (left, right)) in left.iter_mut().zip(right.iter_mut()).enumerate() {
+                osc.set_frequency_hz(frequency(index, sample), SAMPLE_RATE);
+                osc.set_amplitude(0.01);
+                let value = osc.next();
+                *left += value * 0.7;
+                *right += value * 0.7;
+            }
+        }
+        test::black_box((&left, &right));
+    });
+}
+
+#[bench]
+fn bench_sine_bank(b: &mut Bencher) {
+    let mut bank = SineBank::new();
+    let mut oscs: Vec<Osc> = (0..BANK_SIZE).map(|_| Osc::new(0.0, 0.0, 0.0)).collect();
+    let mut frames = [BankFrame::default(); BLOCK];
+    let (mut left, mut right) = ([0_f32; BLOCK], [0_f32; BLOCK]);
+    b.iter(|| {
+        // Filling in the frames is part of the cost, as in the reconstructor
+        for (index, osc) in oscs.iter_mut().enumerate() {
+            for (sample, frame) in frames.iter_mut().enumerate() {
+                osc.set_frequency_hz(frequency(index, sample), SAMPLE_RATE);
+                osc.set_amplitude(0.01);
+                frame.frequencies[index] = osc.frequency();
+                frame.amplitudes[index] = osc.gain();
+                frame.left_gains[index] = 0.7;
+                frame.right_gains[index] = 0.7;
+            }
+        }
+        bank.render(&frames, &mut left, &mut right);
+        test::black_box((&left, &right));
+    });
+}
@@ -0,0 +1,93 @@
+use crate::analyzers::quadratic::PeakAnalyzer;
+use crate::peak::{Peak, MAX_PEAKS};
+use crate::tracker::PeakTracker;
+
+#[derive(Debug, Clone, Copy)]
+pub struct AnalysisSettings {
+    pub fft_size: usize,
+    pub hop_size: usize,
+    pub max_peaks: usize,
+}
+
+impl Default for AnalysisSettings {
+    fn default() -> Self {
+        Self {
+            fft_size: 1024,
+            hop_size: 256,
+            max_peaks: MAX_PEAKS,
+        }
+    }
+}
+
+/// The tracked peaks of one analysis window, time stamped in seconds at the
+/// centre of the window.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Frame {
+    pub time: f64,
+    pub peaks: [Option<Peak>; MAX_PEAKS],
+    pub track_ids: [usize; MAX_PEAKS],
+}
+
+impl Frame {
+    /// Iterates over the (track id, peak) pairs of the active tracks.
+    pub fn tracks(&self) -> impl Iterator<Item = (usize, &Peak)> {
+        self.track_ids
+            .iter()
+            .zip(self.peaks.iter())
+            .filter_map(|(id, peak)| peak.as_ref().map(|peak| (*id, peak)))
+    }
+}
+
+/// Runs the peak analyzer and tracker over a whole signal. Like the plugin,
+/// the analysis window is half of the (zero padded) FFT size.
+pub fn analyze(samples: &[f32], sample_rate: f32, settings: &AnalysisSettings) -> Vec<Frame> {
+    assert!(settings.fft_size.is_power_of_two() && settings.fft_size >= 16);
+    assert!(settings.hop_size > 0);
+    let window_size = settings.fft_size / 2;
+    let mut analyzer = PeakAnalyzer::with_window_size(sample_rate, window_size);
+    let mut tracker = PeakTracker::new();
+    let mut window = vec![0_f32; window_size];
+    let mut frames = Vec::with_capacity(samples.len() / settings.hop_size + 1);
+
+    for start in (0..samples.len()).step_by(settings.hop_size) {
+        for (index, x) in window.iter_mut().enumerate() {
+            *x = samples.get(start + index).copied().unwrap_or(0.0);
+        }
+        let mut peaks = analyzer.get_raw_peaks(&window);
+        for peak in peaks.iter_mut().skip(settings.max_peaks) {
+            *peak = None;
+        }
+        tracker.update_peaks(peaks);
+        frames.push(Frame {
+            time: (start as f64 + 0.5 * window_size as f64) / sample_rate as f64,
+            peaks: *tracker.latest(),
+            track_ids: *tracker.track_ids(),
+        });
+    }
+    frames
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::utils::build_sample;
+
+    #[test]
+    fn test_analyze() {
+        let sample = build_sample(&[(440.0, 0.5, 0.0), (1000.0, 0.25, 0.0)], 48000, 48000.0);
+        let settings = AnalysisSettings {
+            max_peaks: 1,
+            ..AnalysisSettings::default()
+        };
+        let frames = analyze(&sample, 48000.0, &settings);
+        assert_eq!(frames.len(), 188);
+        assert!((frames[1].time - 512.0 / 48000.0).abs() < 1e-9);
+
+        let middle: Vec<(usize, Peak)> = frames[100].tracks().map(|(id, p)| (id, *p)).collect();
+        assert_eq!(middle.len(), 1);
+        assert!((middle[0].1.frequency - 440.0).abs() < 1.0);
+        // The partial keeps its track id from frame to frame
+        let (first_id, _) = frames[1].tracks().next().unwrap();
+        assert_eq!(middle[0].0, first_id);
+    }
+}
@@ -1,10 +1,11 @@
-use crate::peak::Peak;
-use crate::window::apply_hanning_window;
+use crate::peak::{Peak, MAX_PEAKS};
+use crate::utils::wrap_phase;
 use assert_no_alloc::assert_no_alloc;
+use dasp::frame::Mono;
+use dasp::signal::window::hanning;
 use realfft::num_complex::Complex;
 use realfft::{RealFftPlanner, RealToComplex};
-
-const MAX_PEAKS: usize = 20;
+use std::f32::consts::{FRAC_PI_2, PI};
 
 fn find_bin_freq_quadratic(bins: &[Complex<f32>], bin: usize) -> f32 {
     let previous_magnitude = bins[bin - 1].norm();
@@ -15,11 +16,21 @@ fn find_bin_freq_quadratic(bins: &[Complex<f32>], bin: usize) -> f32 {
     bin as f32 + detune
 }
 
-fn find_top_20_bins(bins: &[Complex<f32>]) -> [Option<(usize, f32)>; MAX_PEAKS] {
-    let mut peak_bins: [Option<(usize, f32)>; 256] = [None; 256];
-    let mut peak_index = 0;
-    let threshold = 0.06;
-    let window_size = 512;
+/// The phase of the sinusoid peaking in `bin` as a sine at the centre of the
+/// window, undoing the linear phase of the symmetric window. The FFT is twice
+/// the window size.
+fn find_bin_phase(bins: &[Complex<f32>], bin: usize, window_size: usize) -> f32 {
+    let bin_frequency = PI * bin as f32 / window_size as f32;
+    let center = (window_size - 1) as f32 * 0.5;
+    wrap_phase(bins[bin].arg() + bin_frequency * center + FRAC_PI_2)
+}
+
+/// The loudest peaks above `threshold` as bins and magnitudes, loudest first.
+fn find_top_20_bins(bins: &[Complex<f32>], threshold: f32) -> [Option<(usize, f32)>; MAX_PEAKS] {
+    // Empty slots sort below every peak
+    let level = |peak: &Option<(usize, f32)>| peak.map_or(-1.0, |(_, magnitude)| magnitude);
+    let mut top_20: [Option<(usize, f32)>; MAX_PEAKS] = [None; MAX_PEAKS];
+    let window_size = bins.len() - 1;
     let minimum_bin = 2;
     for bin in minimum_bin..window_size - 1 {
         let previous_magnitude = bins[bin - 1].norm();
@@ -33,86 +44,209 @@ fn find_top_20_bins(bins: &[Complex<f32>]) -> [Option<(usize, f32)>; MAX_PEAKS]
             && magnitude > previous2_magnitude
             && magnitude > next2_magnitude
         {
-            peak_bins[peak_index] = Some((bin, magnitude));
-            peak_index += 1;
-            if peak_index == 256 {
-                break;
+            // Takes the place of an empty slot or else of the quietest peak
+            let quietest = (0..MAX_PEAKS)
+                .min_by(|a, b| level(&top_20[*a]).total_cmp(&level(&top_20[*b])))
+                .unwrap();
+            if magnitude > level(&top_20[quietest]) {
+                top_20[quietest] = Some((bin, magnitude));
             }
         }
     }
-    peak_bins.sort_unstable_by(|a, b| {
-        if a.is_none() {
-            std::cmp::Ordering::Less
-        } else if b.is_none() {
-            return std::cmp::Ordering::Greater;
+    top_20.sort_unstable_by(|a, b| level(b).total_cmp(&level(a)));
+    top_20
+}
+
+pub const DEFAULT_WINDOW_SIZE: usize = 512;
+
+// The quietest bin magnitude taken as a peak at the default window size
+const PEAK_THRESHOLD: f32 = 0.06;
+
+// Bins this close to a peak have its windowed sinusoid subtracted, further
+// out the side lobes of the window are more than 70 dB down
+const SUBTRACT_BINS: usize = 16;
+
+/// The spectrum of the analysis window at `omega` radians per sample from the
+/// frequency of a complex sinusoid starting at phase 0. The window is a
+/// symmetric Hann window, the sum of three Dirichlet kernels.
+fn window_transform(omega: f32, window_size: usize) -> Complex<f32> {
+    let length = window_size as f32;
+    let dirichlet = |omega: f32| {
+        let denominator = (omega * 0.5).sin();
+        if denominator.abs() < 1e-6 {
+            length
         } else {
-            return a.unwrap().1.partial_cmp(&b.unwrap().1).unwrap();
+            (omega * length * 0.5).sin() / denominator
         }
-    });
-    peak_bins.reverse();
-    let mut top_20: [Option<(usize, f32)>; MAX_PEAKS] = [None; MAX_PEAKS];
-    top_20.clone_from_slice(&peak_bins[0..MAX_PEAKS]);
-    top_20
+    };
+    let alpha = 2.0 * PI / (length - 1.0);
+    let magnitude =
+        0.5 * dirichlet(omega) + 0.25 * dirichlet(omega - alpha) + 0.25 * dirichlet(omega + alpha);
+    Complex::from_polar(magnitude, -omega * (length - 1.0) * 0.5)
 }
 
 pub struct PeakAnalyzer {
     plan: std::sync::Arc<dyn RealToComplex<f32>>,
+    window: Vec<f32>,
+    // sum of the squared window, the gain of the window on noise power
+    window_power: f32,
     fft_input: Vec<f32>,
     fft_scratch: Vec<Complex<f32>>,
     fft_output: Vec<Complex<f32>>,
+    // the spectrum without the peaks' sinusoids
+    residual: Vec<Complex<f32>>,
+    // bin magnitudes of the previous frame, for the spectral flux
+    magnitudes: Vec<f32>,
+    flux: f32,
     sample_rate: f32,
 }
 
 impl PeakAnalyzer {
     pub fn new(sample_rate: f32) -> Self {
+        Self::with_window_size(sample_rate, DEFAULT_WINDOW_SIZE)
+    }
+
+    /// The analysis frame is zero padded to an FFT of twice the window size.
+    pub fn with_window_size(sample_rate: f32, window_size: usize) -> Self {
+        assert!(window_size >= 8, "window size must be at least 8 samples");
         let mut planner = RealFftPlanner::<f32>::new();
-        let plan = planner.plan_fft_forward(1024);
+        let plan = planner.plan_fft_forward(window_size * 2);
+        let window: Vec<f32> = hanning::<Mono<f32>>(window_size)
+            .take(window_size)
+            .map(|[w]| w)
+            .collect();
+        let window_power = window.iter().map(|w| w * w).sum();
         let fft_input = plan.make_input_vec();
         let fft_scratch = plan.make_scratch_vec();
         let fft_output = plan.make_output_vec();
+        let magnitudes = vec![0.0; fft_output.len()];
+        let residual = fft_output.clone();
         Self {
             plan,
+            window,
+            window_power,
             fft_input,
             fft_scratch,
             fft_output,
+            residual,
+            magnitudes,
+            flux: 0.0,
             sample_rate,
         }
     }
 
-    pub fn get_raw_peaks(&mut self, input: &[f32; 512]) -> [Option<Peak>; MAX_PEAKS] {
+    pub fn window_size(&self) -> usize {
+        self.window.len()
+    }
+
+    /// How much the spectrum rose from the frame before the last analyzed
+    /// one, summed over all bins in the same units as peak amplitudes.
+    pub fn spectral_flux(&self) -> f32 {
+        self.flux
+    }
+
+    pub fn get_raw_peaks(&mut self, input: &[f32]) -> [Option<Peak>; MAX_PEAKS] {
+        let window_size = self.window_size();
+        assert_eq!(input.len(), window_size);
         assert_no_alloc(|| {
             for x in self.fft_input.iter_mut() {
                 *x = 0.0;
             }
-            let windowed_input = apply_hanning_window::<512>(input);
-            let fft_frame = &mut self.fft_input[0..512];
-            for (x, y) in windowed_input.iter().zip(fft_frame.iter_mut()) {
-                *y = *x;
+            let fft_frame = &mut self.fft_input[0..window_size];
+            for ((x, w), y) in input.iter().zip(self.window.iter()).zip(fft_frame.iter_mut()) {
+                *y = *x * w;
             }
             let _result = self.plan.process_with_scratch(
                 self.fft_input.as_mut_slice(),
                 self.fft_output.as_mut_slice(),
                 self.fft_scratch.as_mut_slice(),
             );
-            let peak_bins = find_top_20_bins(self.fft_output.as_slice());
+            // Amplitudes and the threshold are calibrated for the default
+            // window size
+            let amplitude_scale = DEFAULT_WINDOW_SIZE as f32 / window_size as f32;
+            let peak_bins =
+                find_top_20_bins(self.fft_output.as_slice(), PEAK_THRESHOLD / amplitude_scale);
             let mut peaks: [Option<Peak>; MAX_PEAKS] = [None; MAX_PEAKS];
-            let window_size = 512;
             let freq_per_bin = 0.5 * self.sample_rate / window_size as f32;
 
+            let mut rise = 0.0;
+            for (bin, previous) in self.fft_output.iter().zip(self.magnitudes.iter_mut()) {
+                let magnitude = bin.norm();
+                rise += (magnitude - *previous).max(0.0);
+                *previous = magnitude;
+            }
+            self.flux = rise / 512.0_f32.sqrt() * 0.2 * amplitude_scale;
+
             for (peak, peak_bin_pair) in peaks.iter_mut().zip(peak_bins.iter()) {
                 if let Some((peak_bin, magnitude)) = peak_bin_pair {
                     let frequency = find_bin_freq_quadratic(self.fft_output.as_slice(), *peak_bin)
                         * freq_per_bin;
-                    let amplitude = *magnitude / 512.0_f32.sqrt() * 0.2;
+                    let amplitude = *magnitude / 512.0_f32.sqrt() * 0.2 * amplitude_scale;
+                    let phase = find_bin_phase(self.fft_output.as_slice(), *peak_bin, window_size);
                     *peak = Some(Peak {
                         frequency,
                         amplitude,
+                        phase,
                     });
                 }
             }
             peaks
         })
     }
+
+    /// Measures what is left of the last analyzed spectrum once the windowed
+    /// sinusoids of `peaks` are subtracted, in bands between consecutive
+    /// `edges` in Hz. A level is the standard deviation of white noise with
+    /// the same spectral density.
+    ///
+    /// Each sinusoid's amplitude and phase are measured from the residual at
+    /// its bin, going from the loudest peak down as `get_raw_peaks` returns
+    /// them, so quieter peaks are measured without the leakage of louder
+    /// ones. Any error in a peak's frequency is left in the residual, and the
+    /// mirror image of a sinusoid below 0 Hz is not subtracted, which only
+    /// matters within a few bins of 0 Hz.
+    pub fn residual_levels(&mut self, peaks: &[Option<Peak>], edges: &[f32], levels: &mut [f32]) {
+        let window_size = self.window_size();
+        let freq_per_bin = 0.5 * self.sample_rate / window_size as f32;
+        let bins = self.fft_output.len();
+        let radians_per_bin = PI / window_size as f32;
+        self.residual.copy_from_slice(&self.fft_output);
+        for peak in peaks.iter().flatten() {
+            let position = peak.frequency / freq_per_bin;
+            let peak_bin = position.round() as usize;
+            if peak_bin >= bins {
+                continue;
+            }
+            // A real sinusoid is a complex one at its frequency plus the
+            // conjugate at minus its frequency, whose leakage matters near 0 Hz
+            let kernel =
+                |bin: usize| window_transform((bin as f32 - position) * radians_per_bin, window_size);
+            let image =
+                |bin: usize| window_transform((bin as f32 + position) * radians_per_bin, window_size);
+            // Solves x = c * a + conj(c) * b for the complex amplitude c
+            let (a, b, x) = (kernel(peak_bin), image(peak_bin), self.residual[peak_bin]);
+            let determinant = a.norm_sqr() - b.norm_sqr();
+            if determinant.abs() < f32::EPSILON {
+                continue;
+            }
+            let amplitude = (x * a.conj() - x.conj() * b) / determinant;
+            let low = peak_bin.saturating_sub(SUBTRACT_BINS);
+            let high = (peak_bin + SUBTRACT_BINS + 1).min(bins);
+            for (bin, residual) in self.residual.iter_mut().enumerate().take(high).skip(low) {
+                *residual -= amplitude * kernel(bin) + amplitude.conj() * image(bin);
+            }
+        }
+        for (level, band) in levels.iter_mut().zip(edges.windows(2)) {
+            let low = ((band[0] / freq_per_bin).ceil() as usize).min(bins);
+            let high = ((band[1] / freq_per_bin).ceil() as usize).min(bins);
+            let power: f32 = self.residual[low..high].iter().map(|bin| bin.norm_sqr()).sum();
+            *level = if high > low {
+                (power / (high - low) as f32 / self.window_power).sqrt()
+            } else {
+                0.0
+            };
+        }
+    }
 }
 
 #[cfg(test)]
@@ -128,15 +262,17 @@ mod test {
             48000.0,
         );
         let mut analyzer = PeakAnalyzer::new(48000.0);
-        let peaks_a = analyzer.get_raw_peaks(&sample[0..512].try_into().unwrap());
+        let peaks_a = analyzer.get_raw_peaks(&sample[0..512]);
         let expected = [
             Some(Peak {
                 frequency: 439.69232,
                 amplitude: 1.1020504,
+                phase: 2.1491911,
             }),
             Some(Peak {
                 frequency: 998.8542,
                 amplitude: 0.5557409,
+                phase: 2.0291078,
             }),
             None,
             None,
@@ -159,4 +295,83 @@ mod test {
         ];
         assert_eq!(expected, peaks_a);
     }
+
+    #[test]
+    fn test_threshold() {
+        // The threshold is at an amplitude of about 0.0005 for any window size
+        for window_size in [256, 512, 2048] {
+            let mut analyzer = PeakAnalyzer::with_window_size(48000.0, window_size);
+            let quiet = build_sample(&[(1000.0, 0.0003, 0.0)], window_size, 48000.0);
+            assert!(analyzer.get_raw_peaks(&quiet)[0].is_none(), "{}", window_size);
+            let audible = build_sample(&[(1000.0, 0.001, 0.0)], window_size, 48000.0);
+            assert!(analyzer.get_raw_peaks(&audible)[0].is_some(), "{}", window_size);
+        }
+    }
+
+    #[test]
+    fn test_top_bins() {
+        // A peak every 4 bins, far more than fit, and the loudest near the top
+        let mut bins: Vec<Complex<f32>> = (0..4097)
+            .map(|bin| Complex::new([1.0, 0.5, 0.2, 0.5][bin % 4], 0.0))
+            .collect();
+        bins[4000] = Complex::new(10.0, 0.0);
+        bins[8] = Complex::new(2.0, 0.0);
+        let top = find_top_20_bins(&bins, 0.6);
+        assert_eq!(top[0], Some((4000, 10.0)));
+        assert_eq!(top[1], Some((8, 2.0)));
+        assert!(top[2..].iter().all(|peak| peak.unwrap().1 == 1.0));
+    }
+
+    #[test]
+    fn test_peak_phase() {
+        let mut analyzer = PeakAnalyzer::new(48000.0);
+        for (frequency, phase) in [(440.0, 0.0), (1000.0, 0.3), (2345.6, 0.8)] {
+            let sample = build_sample(&[(frequency, 0.5, phase)], 512, 48000.0);
+            let peak = analyzer.get_raw_peaks(&sample)[0].unwrap();
+            let center = 255.5 * 2.0 * PI * frequency / 48000.0;
+            let expected = wrap_phase(phase * 2.0 * PI + center);
+            assert!(wrap_phase(peak.phase - expected).abs() < 0.05, "{} {}", peak.phase, expected);
+        }
+    }
+
+    #[test]
+    fn test_residual_levels() {
+        let edges = [0.0, 1000.0, 4000.0, 16000.0];
+        let mut levels = [0.0; 3];
+        let mut analyzer = PeakAnalyzer::new(48000.0);
+
+        // The sinusoids are taken out, leaving little more than the error of
+        // their frequency estimates
+        let sample = build_sample(&[(440.0, 1.0, 0.0), (2000.0, 0.5, 0.0)], 512, 48000.0);
+        let peaks = analyzer.get_raw_peaks(&sample[0..512]);
+        analyzer.residual_levels(&peaks, &edges, &mut levels);
+        assert!(levels.iter().all(|level| *level < 0.025), "{:?}", levels);
+
+        // Uniform noise between -0.1 and 0.1 has a standard deviation of 0.1/√3
+        let noise: Vec<f32> = (0..512)
+            .map(|index| crate::drift::random(index) * 0.1)
+            .collect();
+        analyzer.get_raw_peaks(&noise);
+        analyzer.residual_levels(&[], &edges, &mut levels);
+        let expected = 0.1 / 3_f32.sqrt();
+        assert!(
+            levels.iter().all(|level| (level / expected - 1.0).abs() < 0.25),
+            "{:?}",
+            levels
+        );
+        let noise_levels = levels;
+
+        // Noise right next to a loud sinusoid is kept, without its leakage
+        let noisy: Vec<f32> = sample.iter().zip(noise.iter()).map(|(x, y)| x + y).collect();
+        let peaks = analyzer.get_raw_peaks(&noisy);
+        analyzer.residual_levels(&peaks[0..2], &edges, &mut levels);
+        assert!(
+            levels
+                .iter()
+                .zip(noise_levels.iter())
+                .all(|(level, noise_level)| (level / noise_level - 1.0).abs() < 0.25),
+            "{:?}",
+            levels
+        );
+    }
 }
@@ -14,6 +14,14 @@ impl Ringbuffer {
         self.index = (self.index + 1) % self.data.len();
     }
 
+    /// Writes `value` and returns the value written `length` writes before,
+    /// which makes the buffer a delay line.
+    pub fn replace(&mut self, value: f32) -> f32 {
+        let oldest = self.data[self.index];
+        self.write(value);
+        oldest
+    }
+
     pub fn get_reader(&self) -> BufferReader {
         BufferReader {
             data: &self.data,
@@ -61,5 +69,6 @@ mod test {
         assert!((reader.next().unwrap() - 4.0).abs() < f32::EPSILON);
         assert!((reader.next().unwrap() - 5.0).abs() < f32::EPSILON);
         assert!(reader.next().is_none());
+        assert_eq!(buffer.replace(6.0), 2.0);
     }
 }
@@ -0,0 +1,104 @@
+use crate::envelope::SpectralEnvelope;
+use crate::peak::{Peak, MAX_PEAKS};
+
+/// How the amplitudes of one spectrum are applied to the partials of another.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CrossMode {
+    Off,
+    /// The loudest partial takes the amplitude of the other spectrum's
+    /// loudest partial, the second loudest the second loudest and so on
+    Amplitudes,
+    /// Each partial takes the amplitude of the other spectrum's envelope at
+    /// its frequency
+    Envelope,
+}
+
+/// Slot indices of the active peaks, loudest first, and their count.
+pub(crate) fn loudest(peaks: &[Option<Peak>; MAX_PEAKS]) -> ([usize; MAX_PEAKS], usize) {
+    let mut order = [0; MAX_PEAKS];
+    let mut len = 0;
+    for (slot, peak) in peaks.iter().enumerate() {
+        if peak.is_some() {
+            order[len] = slot;
+            len += 1;
+        }
+    }
+    let amplitude = |slot: &usize| peaks[*slot].map_or(0.0, |peak| peak.amplitude);
+    order[0..len].sort_unstable_by(|a, b| amplitude(b).total_cmp(&amplitude(a)));
+    (order, len)
+}
+
+/// Keeps the frequencies and slots of `frequencies` with the amplitudes
+/// taken from `amplitudes`.
+pub fn cross_synthesize(
+    frequencies: &[Option<Peak>; MAX_PEAKS],
+    amplitudes: &[Option<Peak>; MAX_PEAKS],
+    mode: CrossMode,
+) -> [Option<Peak>; MAX_PEAKS] {
+    let mut peaks = *frequencies;
+    match mode {
+        CrossMode::Off => (),
+        CrossMode::Amplitudes => {
+            let (frequency_order, frequency_len) = loudest(frequencies);
+            let (amplitude_order, amplitude_len) = loudest(amplitudes);
+            for (rank, slot) in frequency_order[0..frequency_len].iter().enumerate() {
+                peaks[*slot] = match (peaks[*slot], amplitude_order[0..amplitude_len].get(rank)) {
+                    (Some(peak), Some(amplitude_slot)) => amplitudes[*amplitude_slot].map(|other| Peak {
+                        amplitude: other.amplitude,
+                        ..peak
+                    }),
+                    _ => None,
+                };
+            }
+        }
+        CrossMode::Envelope => {
+            let envelope = SpectralEnvelope::from_peaks(amplitudes);
+            for peak in peaks.iter_mut() {
+                if let Some(current) = peak {
+                    current.amplitude = envelope.amplitude_at(current.frequency);
+                    if current.amplitude <= 0.0 {
+                        *peak = None;
+                    }
+                }
+            }
+        }
+    }
+    peaks
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn peaks(values: &[(usize, f32, f32)]) -> [Option<Peak>; MAX_PEAKS] {
+        let mut peaks = [None; MAX_PEAKS];
+        for (slot, frequency, amplitude) in values {
+            peaks[*slot] = Some(Peak {
+                frequency: *frequency,
+                amplitude: *amplitude,
+                phase: 0.0,
+            });
+        }
+        peaks
+    }
+
+    #[test]
+    fn test_cross_synthesize() {
+        let pad = peaks(&[(0, 220.0, 0.1), (1, 330.0, 0.3), (2, 440.0, 0.2)]);
+        let voice = peaks(&[(4, 200.0, 0.8), (5, 600.0, 0.4)]);
+
+        assert_eq!(cross_synthesize(&pad, &voice, CrossMode::Off), pad);
+
+        let ranked = cross_synthesize(&pad, &voice, CrossMode::Amplitudes);
+        assert_eq!(ranked, peaks(&[(1, 330.0, 0.8), (2, 440.0, 0.4)]));
+
+        let enveloped = cross_synthesize(&pad, &voice, CrossMode::Envelope);
+        assert_eq!(enveloped[0].unwrap().frequency, 220.0);
+        assert!(enveloped[0].unwrap().amplitude < 0.8 && enveloped[0].unwrap().amplitude > 0.7);
+        assert!(enveloped[2].unwrap().amplitude < enveloped[1].unwrap().amplitude);
+
+        // Nothing to take the amplitudes from silences the partials
+        let silence = [None; MAX_PEAKS];
+        assert_eq!(cross_synthesize(&pad, &silence, CrossMode::Envelope), silence);
+    }
+}
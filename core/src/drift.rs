@@ -0,0 +1,83 @@
+use dasp::signal::noise;
+
+/// A random value between -1 and 1 that only depends on the seed.
+pub(crate) fn random(seed: u64) -> f32 {
+    // dasp's noise is a hash of its seed. It increments the seed per sample,
+    // which must not overflow.
+    noise(seed & (u64::MAX >> 1)).next_sample() as f32
+}
+
+/// A slowly evolving random value between -1 and 1. A new random value is
+/// reached `rate` times a second, with a smooth curve in between. At a rate
+/// of 0 the value stays put.
+#[derive(Debug)]
+pub struct Drift {
+    seed: u64,
+    from: f32,
+    to: f32,
+    phase: f32,
+    step: f32,
+}
+
+impl Drift {
+    pub fn new(seed: u64) -> Self {
+        let mut drift = Self {
+            seed,
+            from: 0.0,
+            to: 0.0,
+            phase: 0.0,
+            step: 0.0,
+        };
+        drift.from = drift.next_random();
+        drift.to = drift.next_random();
+        drift
+    }
+
+    fn next_random(&mut self) -> f32 {
+        let value = random(self.seed);
+        self.seed = self.seed.wrapping_add(1);
+        value
+    }
+
+    pub fn set_rate(&mut self, rate: f32, sample_rate: f32) {
+        self.step = rate.max(0.0) / sample_rate;
+    }
+
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> f32 {
+        let curve = self.phase * self.phase * (3.0 - 2.0 * self.phase);
+        let value = self.from + (self.to - self.from) * curve;
+        self.phase += self.step;
+        if self.phase >= 1.0 {
+            self.phase = self.phase.fract();
+            self.from = self.to;
+            self.to = self.next_random();
+        }
+        value
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_drift() {
+        let mut still = Drift::new(1);
+        let start = still.next();
+        assert!((0..1000).all(|_| still.next() == start));
+
+        let mut drift = Drift::new(1);
+        drift.set_rate(10.0, 1000.0);
+        let values: Vec<f32> = (0..1000).map(|_| drift.next()).collect();
+        assert_eq!(values[0], start);
+        assert!(values.iter().all(|x| (-1.0..=1.0).contains(x)));
+        // Smooth: no large jumps between samples
+        assert!(values.windows(2).all(|w| (w[1] - w[0]).abs() < 0.05));
+        assert!(values.iter().any(|x| (x - start).abs() > 0.1));
+
+        let mut same_seed = Drift::new(1);
+        same_seed.set_rate(10.0, 1000.0);
+        assert!(values.iter().all(|x| *x == same_seed.next()));
+    }
+}
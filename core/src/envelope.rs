@@ -0,0 +1,96 @@
+use crate::peak::{Peak, MAX_PEAKS};
+
+/// A spectral envelope through the peaks of a frame: amplitudes are
+/// interpolated linearly over log frequency between neighbouring peaks and
+/// held beyond the lowest and highest peak.
+#[derive(Debug, Clone, Copy)]
+pub struct SpectralEnvelope {
+    // (log2 frequency, amplitude) sorted by frequency
+    points: [(f32, f32); MAX_PEAKS],
+    len: usize,
+}
+
+impl SpectralEnvelope {
+    pub fn from_peaks(peaks: &[Option<Peak>]) -> Self {
+        let mut points = [(0.0, 0.0); MAX_PEAKS];
+        let mut len = 0;
+        for peak in peaks.iter().flatten().filter(|peak| peak.frequency > 0.0) {
+            if len == MAX_PEAKS {
+                break;
+            }
+            points[len] = (peak.frequency.log2(), peak.amplitude);
+            len += 1;
+        }
+        points[0..len].sort_unstable_by(|a, b| a.0.total_cmp(&b.0));
+        Self { points, len }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The envelope amplitude at a frequency, 0 if there were no peaks.
+    pub fn amplitude_at(&self, frequency: f32) -> f32 {
+        let points = &self.points[0..self.len];
+        let (first, last) = match (points.first(), points.last()) {
+            (Some(first), Some(last)) => (first, last),
+            _ => return 0.0,
+        };
+        let position = frequency.max(f32::MIN_POSITIVE).log2();
+        if position <= first.0 {
+            return first.1;
+        }
+        if position >= last.0 {
+            return last.1;
+        }
+        let index = points.partition_point(|point| point.0 <= position);
+        let (below, above) = (points[index - 1], points[index]);
+        let amount = (position - below.0) / (above.0 - below.0);
+        below.1 + (above.1 - below.1) * amount
+    }
+}
+
+/// Re-reads the amplitude of each peak from the envelope of `peaks` at its
+/// frequency times `ratio`, which moves the envelope down by that ratio. A
+/// ratio equal to the transpose keeps the formants where they were.
+pub fn shift_formants(peaks: &[Option<Peak>; MAX_PEAKS], ratio: f32) -> [Option<Peak>; MAX_PEAKS] {
+    let envelope = SpectralEnvelope::from_peaks(peaks);
+    let mut shifted = *peaks;
+    for peak in shifted.iter_mut().flatten() {
+        peak.amplitude = envelope.amplitude_at(peak.frequency * ratio);
+    }
+    shifted
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_spectral_envelope() {
+        assert_eq!(SpectralEnvelope::from_peaks(&[None; MAX_PEAKS]).amplitude_at(440.0), 0.0);
+
+        let mut peaks = [None; MAX_PEAKS];
+        peaks[3] = Some(Peak {
+            frequency: 800.0,
+            amplitude: 0.2,
+            phase: 0.0,
+        });
+        peaks[7] = Some(Peak {
+            frequency: 200.0,
+            amplitude: 0.6,
+            phase: 0.0,
+        });
+        let envelope = SpectralEnvelope::from_peaks(&peaks);
+        assert_eq!(envelope.amplitude_at(100.0), 0.6);
+        assert_eq!(envelope.amplitude_at(2000.0), 0.2);
+        // 400 Hz is halfway between the peaks in log frequency
+        assert!((envelope.amplitude_at(400.0) - 0.4).abs() < 1e-6);
+
+        let shifted = shift_formants(&peaks, 2.0);
+        assert_eq!(shifted[7].unwrap().frequency, 200.0);
+        assert!((shifted[7].unwrap().amplitude - 0.4).abs() < 1e-6);
+        assert_eq!(shifted[3].unwrap().amplitude, 0.2);
+        assert_eq!(shift_formants(&peaks, 1.0), peaks);
+    }
+}
@@ -0,0 +1,158 @@
+use super::sdif::assign_slots;
+use crate::analysis::Frame;
+use crate::peak::{Peak, MAX_PEAKS};
+use std::io::{self, BufRead, BufReader, Read, Write};
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Writes one row per active track per frame.
+pub fn write_frames<W: Write>(frames: &[Frame], mut writer: W) -> io::Result<()> {
+    writeln!(writer, "time,track,frequency,amplitude,phase")?;
+    for frame in frames {
+        for (id, peak) in frame.tracks() {
+            writeln!(
+                writer,
+                "{},{},{},{},{}",
+                frame.time, id, peak.frequency, peak.amplitude, peak.phase
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Reads frames written by `write_frames`, grouping consecutive rows with the
+/// same time into a frame. Columns are found by their header name, and files
+/// without a phase column read with zero phase.
+pub fn read_frames<R: Read>(reader: R) -> io::Result<Vec<Frame>> {
+    let mut lines = BufReader::new(reader).lines();
+    let header = lines
+        .next()
+        .ok_or_else(|| invalid_data("empty CSV file"))??;
+    let names: Vec<&str> = header.split(',').map(str::trim).collect();
+    let column = |name: &str| names.iter().position(|column| *column == name);
+    let missing = || invalid_data("CSV header lacks a required column");
+    let time_column = column("time").ok_or_else(missing)?;
+    let track_column = column("track").ok_or_else(missing)?;
+    let frequency_column = column("frequency").ok_or_else(missing)?;
+    let amplitude_column = column("amplitude").ok_or_else(missing)?;
+    let phase_column = column("phase");
+
+    let empty = Frame {
+        time: 0.0,
+        peaks: [None; MAX_PEAKS],
+        track_ids: [0; MAX_PEAKS],
+    };
+    let mut frames: Vec<Frame> = vec![];
+    let mut rows = vec![];
+    let mut time = None;
+    for line in lines {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+        let field = |column: usize| {
+            fields
+                .get(column)
+                .copied()
+                .ok_or_else(|| invalid_data("CSV row is too short"))
+        };
+        let number = |column: usize| {
+            field(column)?
+                .parse::<f64>()
+                .map_err(|_| invalid_data("CSV field is not a number"))
+        };
+        let row_time = number(time_column)?;
+        let track = field(track_column)?
+            .parse::<usize>()
+            .map_err(|_| invalid_data("CSV track is not an index"))?;
+        let peak = Peak {
+            frequency: number(frequency_column)? as f32,
+            amplitude: number(amplitude_column)? as f32,
+            phase: match phase_column {
+                Some(column) => number(column)? as f32,
+                None => 0.0,
+            },
+        };
+
+        if let Some(time) = time.filter(|time| *time != row_time) {
+            let previous = frames.last().unwrap_or(&empty);
+            frames.push(assign_slots(&mut rows, previous, time));
+            rows.clear();
+        }
+        time = Some(row_time);
+        rows.push((track, peak));
+    }
+    if let Some(time) = time {
+        let previous = frames.last().unwrap_or(&empty);
+        frames.push(assign_slots(&mut rows, previous, time));
+    }
+    Ok(frames)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_write_frames() {
+        let mut frame = Frame {
+            time: 0.25,
+            peaks: [None; MAX_PEAKS],
+            track_ids: [0; MAX_PEAKS],
+        };
+        frame.peaks[1] = Some(Peak {
+            frequency: 440.5,
+            amplitude: 0.5,
+            phase: 1.5,
+        });
+        frame.track_ids[1] = 3;
+        frame.peaks[4] = Some(Peak {
+            frequency: 100.0,
+            amplitude: 0.125,
+            phase: 0.0,
+        });
+        frame.track_ids[4] = 8;
+
+        let mut output = vec![];
+        write_frames(&[frame], &mut output).unwrap();
+        let expected = "time,track,frequency,amplitude,phase
+0.25,3,440.5,0.5,1.5
+0.25,8,100,0.125,0
+";
+        assert_eq!(String::from_utf8(output.clone()).unwrap(), expected);
+
+        let frames = read_frames(output.as_slice()).unwrap();
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].time, 0.25);
+        let mut tracks: Vec<(usize, Peak)> =
+            frames[0].tracks().map(|(id, peak)| (id, *peak)).collect();
+        tracks.sort_by_key(|(id, _)| *id);
+        let mut written: Vec<(usize, Peak)> =
+            frame.tracks().map(|(id, peak)| (id, *peak)).collect();
+        written.sort_by_key(|(id, _)| *id);
+        assert_eq!(tracks, written);
+    }
+
+    #[test]
+    fn test_read_without_phase() {
+        let input = "time,track,frequency,amplitude
+0,1,220,0.5
+0.01,1,221,0.5
+0.01,2,440,0.25
+";
+        let frames = read_frames(input.as_bytes()).unwrap();
+        assert_eq!(frames.len(), 2);
+        let slot = frames[0].track_ids.iter().position(|id| *id == 1).unwrap();
+        assert_eq!(frames[1].track_ids[slot], 1);
+        assert_eq!(frames[1].peaks[slot].unwrap().frequency, 221.0);
+        assert!(frames[1].tracks().all(|(_, peak)| peak.phase == 0.0));
+        assert_eq!(frames[1].tracks().count(), 2);
+
+        let error =
+            read_frames("time,track,frequency,amplitude\n0,x,1,1\n".as_bytes()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
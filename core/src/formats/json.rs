@@ -0,0 +1,66 @@
+use crate::analysis::Frame;
+use crate::peak::Peak;
+use serde::Serialize;
+use std::io::{self, Write};
+
+#[derive(Serialize)]
+struct Track<'a> {
+    track: usize,
+    #[serde(flatten)]
+    peak: &'a Peak,
+}
+
+#[derive(Serialize)]
+struct FrameLine<'a> {
+    time: f64,
+    tracks: Vec<Track<'a>>,
+}
+
+/// Writes one JSON object per line for each frame, listing its active tracks.
+pub fn write_frames<W: Write>(frames: &[Frame], mut writer: W) -> io::Result<()> {
+    for frame in frames {
+        let line = FrameLine {
+            time: frame.time,
+            tracks: frame
+                .tracks()
+                .map(|(track, peak)| Track { track, peak })
+                .collect(),
+        };
+        serde_json::to_writer(&mut writer, &line)?;
+        writeln!(writer)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::peak::MAX_PEAKS;
+
+    #[test]
+    fn test_write_frames() {
+        let mut frame = Frame {
+            time: 0.5,
+            peaks: [None; MAX_PEAKS],
+            track_ids: [0; MAX_PEAKS],
+        };
+        frame.peaks[2] = Some(Peak {
+            frequency: 440.0,
+            amplitude: 0.25,
+            phase: 1.5,
+        });
+        frame.track_ids[2] = 1;
+        let empty = Frame {
+            time: 1.0,
+            peaks: [None; MAX_PEAKS],
+            track_ids: [0; MAX_PEAKS],
+        };
+
+        let mut output = vec![];
+        write_frames(&[frame, empty], &mut output).unwrap();
+        let expected = r#"{"time":0.5,"tracks":[{"track":1,"frequency":440.0,"amplitude":0.25,"phase":1.5}]}
+{"time":1.0,"tracks":[]}
+"#;
+        assert_eq!(String::from_utf8(output).unwrap(), expected);
+    }
+}
@@ -0,0 +1,5 @@
+pub mod csv;
+#[cfg(feature = "serde")]
+pub mod json;
+pub mod sdif;
+pub mod spear;
@@ -0,0 +1,363 @@
+use crate::analysis::Frame;
+use crate::peak::{Peak, MAX_PEAKS};
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+
+// SDIF is big endian throughout and every matrix is padded to 8 bytes.
+// 1TRC matrices hold one row per partial: index, frequency, amplitude, phase.
+const TRC: &[u8; 4] = b"1TRC";
+const FLOAT32: i32 = 0x0004;
+const FLOAT64: i32 = 0x0008;
+const COLUMNS: usize = 4;
+// The time, stream id and matrix count of a frame header, which its size
+// counts along with the matrices
+const FRAME_HEADER_SIZE: usize = 16;
+const MATRIX_HEADER_SIZE: usize = 16;
+
+fn padding(size: usize) -> usize {
+    (8 - size % 8) % 8
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+pub fn write_frames<W: Write>(frames: &[Frame], mut writer: W) -> io::Result<()> {
+    writer.write_all(b"SDIF")?;
+    writer.write_all(&8_i32.to_be_bytes())?;
+    // Specification version and standard types version
+    writer.write_all(&3_i32.to_be_bytes())?;
+    writer.write_all(&1_i32.to_be_bytes())?;
+
+    for frame in frames {
+        let rows = frame.tracks().count();
+        let data_size = rows * COLUMNS * 4;
+        let matrix_size = MATRIX_HEADER_SIZE + data_size + padding(data_size);
+        let frame_size = FRAME_HEADER_SIZE + matrix_size;
+
+        writer.write_all(TRC)?;
+        writer.write_all(&(frame_size as i32).to_be_bytes())?;
+        writer.write_all(&frame.time.to_be_bytes())?;
+        // Stream id and matrix count
+        writer.write_all(&0_i32.to_be_bytes())?;
+        writer.write_all(&1_i32.to_be_bytes())?;
+
+        writer.write_all(TRC)?;
+        writer.write_all(&FLOAT32.to_be_bytes())?;
+        writer.write_all(&(rows as i32).to_be_bytes())?;
+        writer.write_all(&(COLUMNS as i32).to_be_bytes())?;
+        for (id, peak) in frame.tracks() {
+            for value in [id as f32, peak.frequency, peak.amplitude, peak.phase] {
+                writer.write_all(&value.to_be_bytes())?;
+            }
+        }
+        writer.write_all(&[0; 8][0..padding(data_size)])?;
+    }
+    Ok(())
+}
+
+struct SdifReader<R: Read> {
+    reader: R,
+}
+
+impl<R: Read> SdifReader<R> {
+    fn read_bytes<const N: usize>(&mut self) -> io::Result<[u8; N]> {
+        let mut bytes = [0; N];
+        self.reader.read_exact(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    fn read_i32(&mut self) -> io::Result<i32> {
+        Ok(i32::from_be_bytes(self.read_bytes()?))
+    }
+
+    fn read_f32(&mut self) -> io::Result<f32> {
+        Ok(f32::from_be_bytes(self.read_bytes()?))
+    }
+
+    fn read_f64(&mut self) -> io::Result<f64> {
+        Ok(f64::from_be_bytes(self.read_bytes()?))
+    }
+
+    fn read_size(&mut self) -> io::Result<usize> {
+        usize::try_from(self.read_i32()?).map_err(|_| invalid_data("negative size"))
+    }
+
+    fn skip(&mut self, size: usize) -> io::Result<()> {
+        let skipped = io::copy(&mut (&mut self.reader).take(size as u64), &mut io::sink())?;
+        if skipped < size as u64 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        Ok(())
+    }
+
+    /// Returns `None` at the end of the file.
+    fn read_signature(&mut self) -> io::Result<Option<[u8; 4]>> {
+        let mut signature = [0; 4];
+        let mut read = 0;
+        while read < signature.len() {
+            match self.reader.read(&mut signature[read..])? {
+                0 if read == 0 => return Ok(None),
+                0 => return Err(io::ErrorKind::UnexpectedEof.into()),
+                n => read += n,
+            }
+        }
+        Ok(Some(signature))
+    }
+
+    /// Reads the rows of a matrix as (index, frequency, amplitude, phase) and
+    /// returns the number of bytes consumed. Without a phase column phases
+    /// are 0. Matrices larger than the `remaining` bytes of their frame are
+    /// invalid.
+    fn read_trc_rows(
+        &mut self,
+        rows: &mut Vec<(usize, Peak)>,
+        remaining: usize,
+    ) -> io::Result<usize> {
+        let signature = self.read_bytes::<4>()?;
+        let data_type = self.read_i32()?;
+        let num_rows = self.read_size()?;
+        let num_columns = self.read_size()?;
+        let value_size = (data_type & 0xff) as usize;
+        let size = num_rows
+            .checked_mul(num_columns)
+            .and_then(|values| values.checked_mul(value_size))
+            .and_then(|data_size| data_size.checked_add(padding(data_size)))
+            .and_then(|data_size| data_size.checked_add(MATRIX_HEADER_SIZE))
+            .filter(|size| *size <= remaining)
+            .ok_or_else(|| invalid_data("matrix larger than its frame"))?;
+        let data_size = num_rows * num_columns * value_size;
+        let readable = &signature == TRC
+            && num_columns >= 3
+            && (data_type == FLOAT32 || data_type == FLOAT64);
+        if !readable {
+            self.skip(size - MATRIX_HEADER_SIZE)?;
+            return Ok(size);
+        }
+        for _ in 0..num_rows {
+            let mut values = [0_f64; 4];
+            for column in 0..num_columns {
+                let value = match data_type {
+                    FLOAT32 => self.read_f32()? as f64,
+                    _ => self.read_f64()?,
+                };
+                if column < values.len() {
+                    values[column] = value;
+                }
+            }
+            let [index, frequency, amplitude, phase] = values;
+            rows.push((
+                index.max(0.0) as usize,
+                Peak {
+                    frequency: frequency as f32,
+                    amplitude: amplitude as f32,
+                    phase: phase as f32,
+                },
+            ));
+        }
+        self.skip(padding(data_size))?;
+        Ok(size)
+    }
+}
+
+/// Places partials in frame slots so that a partial index stays in the same
+/// slot from frame to frame. Beyond `MAX_PEAKS` partials the quietest new
+/// ones are dropped.
+pub(crate) fn assign_slots(rows: &mut [(usize, Peak)], previous: &Frame, time: f64) -> Frame {
+    let mut frame = Frame {
+        time,
+        peaks: [None; MAX_PEAKS],
+        track_ids: [0; MAX_PEAKS],
+    };
+    rows.sort_by(|a, b| b.1.amplitude.total_cmp(&a.1.amplitude));
+    let previous_slots: HashMap<usize, usize> = previous
+        .peaks
+        .iter()
+        .zip(previous.track_ids.iter())
+        .enumerate()
+        .filter(|(_, (peak, _))| peak.is_some())
+        .map(|(slot, (_, id))| (*id, slot))
+        .collect();
+    let mut new_rows = vec![];
+    for (index, peak) in rows.iter() {
+        match previous_slots.get(index) {
+            Some(slot) if frame.peaks[*slot].is_none() => {
+                frame.peaks[*slot] = Some(*peak);
+                frame.track_ids[*slot] = *index;
+            }
+            _ => new_rows.push((*index, *peak)),
+        }
+    }
+    let mut free_slots = (0..MAX_PEAKS).filter(|slot| previous.peaks[*slot].is_none());
+    let mut reused_slots = (0..MAX_PEAKS).filter(|slot| previous.peaks[*slot].is_some());
+    for (index, peak) in new_rows {
+        let slot = free_slots
+            .next()
+            .or_else(|| reused_slots.find(|slot| frame.peaks[*slot].is_none()));
+        if let Some(slot) = slot {
+            frame.peaks[slot] = Some(peak);
+            frame.track_ids[slot] = index;
+        }
+    }
+    frame
+}
+
+/// Reads the 1TRC frames of the first stream that has any. Other frame and
+/// matrix types are skipped.
+pub fn read_frames<R: Read>(reader: R) -> io::Result<Vec<Frame>> {
+    let mut reader = SdifReader { reader };
+    if reader.read_signature()? != Some(*b"SDIF") {
+        return Err(invalid_data("not an SDIF file"));
+    }
+    let header_size = reader.read_size()?;
+    reader.skip(header_size)?;
+
+    let mut frames: Vec<Frame> = vec![];
+    let mut stream = None;
+    let mut rows = vec![];
+    while let Some(signature) = reader.read_signature()? {
+        let frame_size = reader.read_size()?;
+        if &signature != TRC {
+            reader.skip(frame_size)?;
+            continue;
+        }
+        let time = reader.read_f64()?;
+        let stream_id = reader.read_i32()?;
+        let matrix_count = reader.read_size()?;
+        let matrices_size = frame_size
+            .checked_sub(FRAME_HEADER_SIZE)
+            .ok_or_else(|| invalid_data("frame too small"))?;
+        if *stream.get_or_insert(stream_id) != stream_id {
+            reader.skip(matrices_size)?;
+            continue;
+        }
+        let mut consumed = FRAME_HEADER_SIZE;
+        rows.clear();
+        for _ in 0..matrix_count {
+            let remaining = frame_size.saturating_sub(consumed);
+            consumed += reader.read_trc_rows(&mut rows, remaining)?;
+        }
+        reader.skip(frame_size.saturating_sub(consumed))?;
+
+        let empty = Frame {
+            time,
+            peaks: [None; MAX_PEAKS],
+            track_ids: [0; MAX_PEAKS],
+        };
+        let previous = frames.last().unwrap_or(&empty);
+        frames.push(assign_slots(&mut rows, previous, time));
+    }
+    Ok(frames)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn frame(time: f64, tracks: &[(usize, usize, f32, f32)]) -> Frame {
+        let mut frame = Frame {
+            time,
+            peaks: [None; MAX_PEAKS],
+            track_ids: [0; MAX_PEAKS],
+        };
+        for (slot, id, frequency, amplitude) in tracks {
+            frame.peaks[*slot] = Some(Peak {
+                frequency: *frequency,
+                amplitude: *amplitude,
+                phase: -1.5,
+            });
+            frame.track_ids[*slot] = *id;
+        }
+        frame
+    }
+
+    #[test]
+    fn test_write_and_read_frames() {
+        let frames = [
+            frame(0.0, &[(0, 4, 440.0, 0.5), (1, 5, 880.0, 0.25)]),
+            frame(0.01, &[(1, 5, 890.0, 0.125)]),
+            frame(0.02, &[(0, 6, 100.0, 0.5), (1, 5, 900.0, 0.125)]),
+        ];
+        let mut file = vec![];
+        write_frames(&frames, &mut file).unwrap();
+        assert_eq!(file.len() % 8, 0);
+
+        // Unknown frames are skipped
+        file.extend_from_slice(b"1NVT");
+        file.extend_from_slice(&8_i32.to_be_bytes());
+        file.extend_from_slice(&[0; 8]);
+
+        let read = read_frames(file.as_slice()).unwrap();
+        assert_eq!(read, frames);
+    }
+
+    #[test]
+    fn test_trc_layout() {
+        let mut file = vec![];
+        file.extend_from_slice(b"SDIF");
+        for value in [8_i32, 3, 1] {
+            file.extend_from_slice(&value.to_be_bytes());
+        }
+        let frames = [
+            (0.5_f64, vec![(4.0, 440.0, 0.5, 0.0), (5.0, 880.0, 0.25, 1.0)]),
+            (0.75, vec![(5.0, 890.0_f32, 0.125_f32, 2.0_f32)]),
+        ];
+        for (time, rows) in &frames {
+            // The size counts everything after itself
+            let size = 16 + 16 + rows.len() * 16;
+            file.extend_from_slice(b"1TRC");
+            file.extend_from_slice(&(size as i32).to_be_bytes());
+            file.extend_from_slice(&time.to_be_bytes());
+            file.extend_from_slice(&0_i32.to_be_bytes());
+            file.extend_from_slice(&1_i32.to_be_bytes());
+            file.extend_from_slice(b"1TRC");
+            for value in [4, rows.len() as i32, 4] {
+                file.extend_from_slice(&value.to_be_bytes());
+            }
+            for (index, frequency, amplitude, phase) in rows {
+                for value in [index, frequency, amplitude, phase] {
+                    file.extend_from_slice(&value.to_be_bytes());
+                }
+            }
+        }
+
+        let read = read_frames(file.as_slice()).unwrap();
+        assert_eq!(read.len(), 2);
+        assert_eq!(read[0].time, 0.5);
+        assert_eq!(read[0].track_ids[0..2], [4, 5]);
+        assert_eq!(read[0].peaks[1].unwrap().phase, 1.0);
+        assert_eq!(read[1].peaks[1].unwrap().frequency, 890.0);
+        let mut written = vec![];
+        write_frames(&read, &mut written).unwrap();
+        assert_eq!(written, file);
+
+        // A frame too small for its own header
+        let mut corrupt = file[0..16].to_vec();
+        corrupt.extend_from_slice(b"1TRC");
+        corrupt.extend_from_slice(&4_i32.to_be_bytes());
+        corrupt.extend_from_slice(&[0; 16]);
+        assert!(read_frames(corrupt.as_slice()).is_err());
+
+        // Matrices that don't fit in their frame, with and without
+        // overflowing the size
+        for dimension in [1, i32::MAX] {
+            let mut corrupt = file[0..16].to_vec();
+            corrupt.extend_from_slice(b"1TRC");
+            corrupt.extend_from_slice(&32_i32.to_be_bytes());
+            corrupt.extend_from_slice(&0.5_f64.to_be_bytes());
+            corrupt.extend_from_slice(&0_i32.to_be_bytes());
+            corrupt.extend_from_slice(&1_i32.to_be_bytes());
+            corrupt.extend_from_slice(b"1TRC");
+            for value in [4, dimension, dimension] {
+                corrupt.extend_from_slice(&value.to_be_bytes());
+            }
+            let err = read_frames(corrupt.as_slice()).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        }
+    }
+
+    #[test]
+    fn test_read_invalid() {
+        assert!(read_frames(&b"RIFF0000"[..]).is_err());
+    }
+}
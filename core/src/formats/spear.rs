@@ -0,0 +1,74 @@
+use crate::analysis::Frame;
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+/// Writes frames in SPEAR's "par-text-frame-format". SPEAR wants partial
+/// indices to count up from 0, so track ids are renumbered in order of
+/// appearance.
+pub fn write_frames<W: Write>(frames: &[Frame], mut writer: W) -> io::Result<()> {
+    let mut indices: HashMap<usize, usize> = HashMap::new();
+    for frame in frames {
+        for (id, _) in frame.tracks() {
+            let next_index = indices.len();
+            indices.entry(id).or_insert(next_index);
+        }
+    }
+    writeln!(writer, "par-text-frame-format")?;
+    writeln!(writer, "point-type index frequency amplitude")?;
+    writeln!(writer, "partials-count {}", indices.len())?;
+    writeln!(writer, "frame-count {}", frames.len())?;
+    writeln!(writer, "frame-data")?;
+    for frame in frames {
+        write!(writer, "{:.6} {}", frame.time, frame.tracks().count())?;
+        for (id, peak) in frame.tracks() {
+            write!(
+                writer,
+                " {} {:.6} {:.6}",
+                indices[&id], peak.frequency, peak.amplitude
+            )?;
+        }
+        writeln!(writer)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::peak::{Peak, MAX_PEAKS};
+
+    #[test]
+    fn test_write_frames() {
+        let mut first = Frame {
+            time: 0.0,
+            peaks: [None; MAX_PEAKS],
+            track_ids: [0; MAX_PEAKS],
+        };
+        first.peaks[2] = Some(Peak {
+            frequency: 440.0,
+            amplitude: 0.5,
+            phase: 0.0,
+        });
+        first.track_ids[2] = 7;
+        let mut second = first;
+        second.time = 0.5;
+        second.peaks[0] = Some(Peak {
+            frequency: 220.0,
+            amplitude: 0.25,
+            phase: 0.0,
+        });
+        second.track_ids[0] = 9;
+
+        let mut output = vec![];
+        write_frames(&[first, second], &mut output).unwrap();
+        let expected = "par-text-frame-format
+point-type index frequency amplitude
+partials-count 2
+frame-count 2
+frame-data
+0.000000 1 0 440.000000 0.500000
+0.500000 2 1 220.000000 0.250000 0 440.000000 0.500000
+";
+        assert_eq!(String::from_utf8(output).unwrap(), expected);
+    }
+}
@@ -0,0 +1,156 @@
+use crate::peak::{Peak, MAX_PEAKS};
+
+pub const MAX_FREEZE_FRAMES: usize = 32;
+
+/// How the frames of the history are combined into a frozen spectrum.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FreezeMode {
+    Average,
+    Median,
+}
+
+/// The most recent tracked frames, kept so that freezing can settle on a
+/// stable spectrum rather than a single (possibly transient) frame. The
+/// tracker keeps a partial in the same slot, so frames are combined slot by
+/// slot, as long as the slot still holds the same track.
+pub struct FreezeHistory {
+    frames: [[Option<Peak>; MAX_PEAKS]; MAX_FREEZE_FRAMES],
+    track_ids: [[usize; MAX_PEAKS]; MAX_FREEZE_FRAMES],
+    next: usize,
+    len: usize,
+}
+
+impl Default for FreezeHistory {
+    fn default() -> Self {
+        Self {
+            frames: [[None; MAX_PEAKS]; MAX_FREEZE_FRAMES],
+            track_ids: [[0; MAX_PEAKS]; MAX_FREEZE_FRAMES],
+            next: 0,
+            len: 0,
+        }
+    }
+}
+
+impl FreezeHistory {
+    /// `track_ids` are the tracker's ids of `peaks`, slot for slot.
+    pub fn push(&mut self, peaks: &[Option<Peak>; MAX_PEAKS], track_ids: &[usize; MAX_PEAKS]) {
+        self.frames[self.next] = *peaks;
+        self.track_ids[self.next] = *track_ids;
+        self.next = (self.next + 1) % MAX_FREEZE_FRAMES;
+        self.len = (self.len + 1).min(MAX_FREEZE_FRAMES);
+    }
+
+    /// Combines the last `count` frames. Only the latest track in a slot is
+    /// kept, a frame where the slot is empty or holds another track counts
+    /// as silence for the amplitude but not for the frequency.
+    pub fn spectrum(&self, count: usize, mode: FreezeMode) -> [Option<Peak>; MAX_PEAKS] {
+        let count = count.clamp(1, MAX_FREEZE_FRAMES).min(self.len);
+        let mut spectrum = [None; MAX_PEAKS];
+        if count == 0 {
+            return spectrum;
+        }
+        let mut frequencies = [0_f32; MAX_FREEZE_FRAMES];
+        let mut amplitudes = [0_f32; MAX_FREEZE_FRAMES];
+        let frame_index = |age| (self.next + MAX_FREEZE_FRAMES - 1 - age) % MAX_FREEZE_FRAMES;
+        for (slot, peak) in spectrum.iter_mut().enumerate() {
+            let track = (0..count)
+                .map(frame_index)
+                .find(|index| self.frames[*index][slot].is_some())
+                .map(|index| self.track_ids[index][slot]);
+            let mut present = 0;
+            for (age, frame_amplitude) in amplitudes.iter_mut().take(count).enumerate() {
+                let index = frame_index(age);
+                *frame_amplitude = 0.0;
+                if track != Some(self.track_ids[index][slot]) {
+                    continue;
+                }
+                if let Some(Peak { frequency, amplitude, .. }) = self.frames[index][slot] {
+                    frequencies[present] = frequency;
+                    *frame_amplitude = amplitude;
+                    present += 1;
+                }
+            }
+            if present == 0 {
+                continue;
+            }
+            let frequencies = &mut frequencies[0..present];
+            let amplitudes = &mut amplitudes[0..count];
+            *peak = match mode {
+                FreezeMode::Average => Some(Peak {
+                    frequency: frequencies.iter().sum::<f32>() / present as f32,
+                    amplitude: amplitudes.iter().sum::<f32>() / count as f32,
+                    phase: 0.0,
+                }),
+                FreezeMode::Median => {
+                    let amplitude = median(amplitudes);
+                    (amplitude > 0.0).then(|| Peak {
+                        frequency: median(frequencies),
+                        amplitude,
+                        phase: 0.0,
+                    })
+                }
+            };
+        }
+        spectrum
+    }
+}
+
+fn median(values: &mut [f32]) -> f32 {
+    values.sort_unstable_by(f32::total_cmp);
+    // The two middle values coincide for an odd count
+    (values[(values.len() - 1) / 2] + values[values.len() / 2]) / 2.0
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_freeze_history() {
+        let mut history = FreezeHistory::default();
+        assert_eq!(history.spectrum(4, FreezeMode::Average), [None; MAX_PEAKS]);
+
+        // The 600 Hz frame is another partial that passed through the slot
+        let frames = [(440.0, 0.5, 1), (442.0, 0.5, 1), (600.0, 0.1, 2), (441.0, 0.5, 1)];
+        for (frequency, amplitude, track_id) in frames {
+            let mut peaks = [None; MAX_PEAKS];
+            peaks[0] = Some(Peak {
+                frequency,
+                amplitude,
+                phase: 0.0,
+            });
+            let mut track_ids = [0; MAX_PEAKS];
+            track_ids[0] = track_id;
+            history.push(&peaks, &track_ids);
+        }
+        let mut transient = [None; MAX_PEAKS];
+        transient[1] = Some(Peak {
+            frequency: 3000.0,
+            amplitude: 1.0,
+            phase: 0.0,
+        });
+        transient[0] = Some(Peak {
+            frequency: 441.0,
+            amplitude: 0.5,
+            phase: 0.0,
+        });
+        let mut track_ids = [0; MAX_PEAKS];
+        track_ids[0] = 1;
+        track_ids[1] = 3;
+        history.push(&transient, &track_ids);
+
+        let latest = history.spectrum(1, FreezeMode::Average);
+        assert_eq!(latest, transient);
+
+        let average = history.spectrum(5, FreezeMode::Average);
+        assert!((average[0].unwrap().frequency - 441.0).abs() < 1e-3);
+        assert!((average[0].unwrap().amplitude - 0.4).abs() < 1e-6);
+        assert!((average[1].unwrap().amplitude - 0.2).abs() < 1e-6);
+
+        // The one frame transient is rejected
+        let median = history.spectrum(5, FreezeMode::Median);
+        assert_eq!(median[0].unwrap().frequency, 441.0);
+        assert_eq!(median[0].unwrap().amplitude, 0.5);
+        assert!(median[1].is_none());
+    }
+}
@@ -1,14 +1,34 @@
 #![feature(generic_const_exprs)]
+#![feature(portable_simd)]
 
+pub mod analysis;
 pub mod analyzers;
 pub mod buffer;
+pub mod cross;
+pub mod drift;
+pub mod envelope;
+pub mod formats;
+pub mod freeze;
+pub mod limiter;
+pub mod noise;
 pub mod osc;
+pub mod osc_sender;
+pub mod partial_filter;
+pub mod pan;
 pub mod peak;
+pub mod playback;
+pub mod quantize;
 pub mod reconstructor;
+pub mod render;
+pub mod scala;
+pub mod sine_bank;
 pub mod smooth;
+pub mod snapshot;
 pub mod tracker;
+pub mod transient;
 pub mod utils;
 pub mod voice;
+pub mod wav;
 pub mod window;
 
 use assert_no_alloc::*;
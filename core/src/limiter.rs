@@ -0,0 +1,157 @@
+use crate::buffer::Ringbuffer;
+use crate::smooth::{SmoothedValue, SmoothingCurve};
+
+/// The samples the limiter looks ahead, which delays the output in every
+/// mode so the latency doesn't change with it.
+pub const LOOKAHEAD: usize = 64;
+// The gain recovers from a reduction over about this long in seconds
+const RELEASE: f32 = 0.05;
+// Soft clipping starts to bend the signal above this level
+const KNEE: f32 = 0.5;
+// Gain changes ramp over this long in seconds, so automation doesn't step
+const GAIN_GLIDE: f32 = 0.02;
+
+/// How the output is kept between -1 and 1.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LimiterMode {
+    HardClip,
+    /// Follows the signal up to the knee and bends smoothly towards 1 above
+    SoftClip,
+    /// Turns the gain down ahead of peaks and back up slowly after them
+    Lookahead,
+}
+
+fn soft_clip(x: f32) -> f32 {
+    let level = x.abs();
+    if level <= KNEE {
+        x
+    } else {
+        (KNEE + (1.0 - KNEE) * ((level - KNEE) / (1.0 - KNEE)).tanh()).copysign(x)
+    }
+}
+
+/// The final gain and clipping of a stereo output.
+pub struct Limiter {
+    mode: LimiterMode,
+    gain: SmoothedValue,
+    delay: [Ringbuffer; 2],
+    // the gain each of the delayed samples and the incoming one needs,
+    // oldest first from `next`
+    needed: [f32; LOOKAHEAD + 1],
+    next: usize,
+    reduction: f32,
+    release: f32,
+}
+
+impl Limiter {
+    pub fn new(sample_rate: f32) -> Self {
+        let mut gain = SmoothedValue::new(1.0, (GAIN_GLIDE * sample_rate) as usize);
+        gain.set_curve(SmoothingCurve::Decibels);
+        Self {
+            mode: LimiterMode::HardClip,
+            gain,
+            delay: [Ringbuffer::new(LOOKAHEAD), Ringbuffer::new(LOOKAHEAD)],
+            needed: [1.0; LOOKAHEAD + 1],
+            next: 0,
+            reduction: 1.0,
+            release: 1.0 - (-1.0 / (RELEASE * sample_rate)).exp(),
+        }
+    }
+
+    pub fn set_mode(&mut self, mode: LimiterMode) {
+        self.mode = mode;
+    }
+
+    /// The gain in dB applied before clipping or limiting.
+    pub fn set_gain(&mut self, db: f32) {
+        self.gain.set_target(10_f32.powf(db / 20.0));
+    }
+
+    /// Delays both channels by `LOOKAHEAD` samples, applies the gain and
+    /// keeps them between -1 and 1.
+    pub fn process(&mut self, left: &mut [f32], right: &mut [f32]) {
+        let [left_delay, right_delay] = &mut self.delay;
+        for (left, right) in left.iter_mut().zip(right.iter_mut()) {
+            let gain = self.gain.next();
+            let input = (*left * gain, *right * gain);
+            let (delayed_left, delayed_right) =
+                (left_delay.replace(input.0), right_delay.replace(input.1));
+            let (out_left, out_right) = match self.mode {
+                LimiterMode::HardClip => (delayed_left, delayed_right),
+                LimiterMode::SoftClip => (soft_clip(delayed_left), soft_clip(delayed_right)),
+                LimiterMode::Lookahead => {
+                    let peak = input.0.abs().max(input.1.abs());
+                    self.needed[self.next] = if peak > 1.0 { 1.0 / peak } else { 1.0 };
+                    self.next = (self.next + 1) % self.needed.len();
+                    // Turn down steeply enough for every delayed sample to
+                    // reach the gain it needs by the time it leaves the delay
+                    let needed = &self.needed;
+                    let reduction = self.reduction;
+                    let (slope, target) = (0..needed.len())
+                        .map(|age| {
+                            let gain = needed[(self.next + LOOKAHEAD - age) % needed.len()];
+                            ((gain - reduction) / (LOOKAHEAD + 1 - age) as f32, gain)
+                        })
+                        .fold((0_f32, 1_f32), |(slope, target), (step, gain)| {
+                            (slope.min(step), target.min(gain))
+                        });
+                    if slope < 0.0 {
+                        self.reduction += slope;
+                    } else {
+                        self.reduction += (target - self.reduction) * self.release;
+                    }
+                    (delayed_left * self.reduction, delayed_right * self.reduction)
+                }
+            };
+            *left = out_left.clamp(-1.0, 1.0);
+            *right = out_right.clamp(-1.0, 1.0);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::utils::build_sample;
+
+    #[test]
+    fn test_limiter() {
+        let input = build_sample(&[(100.0, 0.5, 0.0)], 4800, 48000.0);
+        let run = |mode: LimiterMode, db: f32| {
+            let mut limiter = Limiter::new(48000.0);
+            limiter.set_mode(mode);
+            limiter.set_gain(db);
+            let (mut left, mut right) = (input.clone(), input.clone());
+            limiter.process(&mut left, &mut right);
+            assert_eq!(left, right);
+            left
+        };
+
+        // Quiet signals pass unchanged, only delayed
+        for mode in [LimiterMode::HardClip, LimiterMode::SoftClip, LimiterMode::Lookahead] {
+            let output = run(mode, 0.0);
+            assert!(output[0..LOOKAHEAD].iter().all(|x| *x == 0.0));
+            assert_eq!(output[LOOKAHEAD..], input[0..input.len() - LOOKAHEAD]);
+        }
+
+        // 12 dB up the sine peaks at 2
+        let clipped = run(LimiterMode::HardClip, 12.0);
+        assert!(clipped.iter().filter(|x| x.abs() == 1.0).count() > 100);
+        let soft = run(LimiterMode::SoftClip, 12.0);
+        assert!(soft.iter().all(|x| x.abs() < 1.0));
+        // The limiter turns the gain down in time, so nothing is clipped
+        let limited = run(LimiterMode::Lookahead, 12.0);
+        assert!(limited.iter().all(|x| x.abs() <= 1.0 + 1e-6));
+        let peak = limited.iter().fold(0_f32, |peak, x| peak.max(x.abs()));
+        assert!(peak > 0.95);
+
+        // Gain changes ramp instead of stepping
+        let mut limiter = Limiter::new(48000.0);
+        limiter.set_gain(-12.0);
+        let (mut left, mut right) = (vec![0.5_f32; 4800], vec![0.5_f32; 4800]);
+        limiter.process(&mut left, &mut right);
+        let steps = left[LOOKAHEAD..].windows(2).map(|pair| (pair[1] - pair[0]).abs());
+        assert!(steps.fold(0_f32, f32::max) < 0.01);
+        assert!((left[4799] - 0.5 * 10_f32.powf(-12.0 / 20.0)).abs() < 1e-3);
+    }
+}
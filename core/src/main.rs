@@ -1 +1,379 @@
-fn main() {}
+use core::analysis::{analyze, AnalysisSettings};
+#[cfg(feature = "serde")]
+use core::formats::json;
+use core::formats::{csv, sdif, spear};
+use core::limiter::LimiterMode;
+use core::osc::Waveform;
+use core::pan::PanMode;
+use core::partial_filter::{Band, Harmonics, PartialFilter};
+use core::peak::MAX_PEAKS;
+use core::quantize::{Quantize, Scale, ScaleKind};
+use core::render::{render, RenderSettings};
+use core::scala::Tuning;
+use core::smooth::SmoothingCurve;
+use core::wav;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::BufWriter;
+use std::process::ExitCode;
+use std::str::FromStr;
+
+const USAGE: &str = "Usage:
+  core analyze [options] <input.wav> <output>
+      Writes the partial tracks of a WAV file.
+      --format <name>  sdif (1TRC), spear (SPEAR's text frame format), csv, or
+                       json (JSON lines, needs the serde feature); by default
+                       picked from the output extension, falling back to spear
+      --fft-size <n>   FFT size, a power of 2; the window is half of it (default 1024)
+      --hop <n>        Hop size in samples (default 256)
+      --peaks <n>      Maximum number of peaks per frame, up to 20 (default 20)
+
+  core resynth [options] <input.wav> <output.wav>
+      Renders a WAV file through the resynthesizer to a stereo WAV file.
+      --transpose <octaves>  Between -2 and 2 (default 0)
+      --preserve-formants <true|false>
+                             Keep the spectral envelope in place when
+                             transposing (default false)
+      --formant-shift <octaves>
+                             Move the spectral envelope, between -2 and 2
+                             (default 0)
+      --shift <hz>           Add to the frequency of every partial (default 0)
+      --stretch <amount>     Spread partials apart above 1 or squeeze them
+                             together below 1, between 0.25 and 4 (default 1)
+      --pivot <hz>           The frequency that stays in place when
+                             stretching (default 261.63)
+      --harmonics <which>    Keep all, odd or even harmonics of the detected
+                             fundamental (default all)
+      --band <mode>          Keep the partials inside or outside a frequency
+                             band (default off)
+      --band-low <hz>        Lower edge of the band (default 200)
+      --band-high <hz>       Upper edge of the band (default 2000)
+      --tilt <db>            Spectral tilt in dB per octave around 1 kHz
+                             (default 0)
+      --filter-depth <amount>
+                             How far filtered partials are turned down,
+                             between 0 and 1 (default 1)
+      --frequency-glide <ms> How long partial frequencies take to follow the
+                             input; long glides blur (default 64 samples)
+      --amplitude-glide <ms> The same for partial amplitudes
+      --amplitude-curve <curve>
+                             Shape of amplitude glides: linear, exponential
+                             or db (default linear)
+      --waveform <shape>     Play partials as sine, saw, square or triangle
+                             waves, or loop a WAV file as a single cycle
+                             wavetable (default sine)
+      --quantize <scale>     Snap partials to a chromatic, major, minor or
+                             pentatonic scale, to harmonics, to a comma
+                             separated list of cents within an octave, or to
+                             a Scala .scl file (default off)
+      --kbm <file>           Scala keyboard mapping for a .scl file, whose
+                             middle note is the root (default middle C at
+                             A = 440 Hz)
+      --root <hz>            Root of the scale or fundamental of the harmonics
+                             (default 261.63)
+      --quantize-amount <amount>
+                             How far partials snap, between 0 and 1 (default 1)
+      --detune <amount>      Random detune between 0 and 1 (default 0)
+      --drift-rate <hz>      How fast the random detune moves (default 0)
+      --noise <amount>       Level of the residual noise between 0 and 1 (default 0)
+      --transients <amount>  Dry input let through at transients, 0 to 1 (default 0)
+      --freeze-at <seconds>  Freeze the spectrum from this time on
+      --partials <n>         Number of partials to resynthesize, up to 20 (default 20)
+      --pan <mode>           Spread partials by index, frequency, random or
+                             alternating (default index)
+      --width <amount>       Stereo width between 0 (mono) and 1 (default 0)
+      --mix <amount>         Dry/wet mix between 0 (dry) and 1 (wet) (default 1)
+      --gain <db>            Output gain before the limiter (default 0)
+      --limiter <mode>       Keep the output in range with a hard clip, soft
+                             clip or lookahead limiter (default hard)
+      --block-size <n>       Processing block size in samples (default 256)
+      --seed <n>             Seed for the random detune and drift (default 0)";
+
+struct Options {
+    positional: Vec<String>,
+    named: HashMap<String, String>,
+}
+
+impl Options {
+    fn parse(args: &[String]) -> Result<Self, String> {
+        let mut positional = vec![];
+        let mut named = HashMap::new();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            if let Some(name) = arg.strip_prefix("--") {
+                let value = args
+                    .next()
+                    .ok_or_else(|| format!("missing value for --{}", name))?;
+                named.insert(name.to_string(), value.clone());
+            } else {
+                positional.push(arg.clone());
+            }
+        }
+        Ok(Self { positional, named })
+    }
+
+    fn get<T: FromStr>(&self, name: &str, default: T) -> Result<T, String> {
+        match self.named.get(name) {
+            Some(value) => value
+                .parse()
+                .map_err(|_| format!("invalid value for --{}: {}", name, value)),
+            None => Ok(default),
+        }
+    }
+
+    fn check_names(&self, allowed: &[&str]) -> Result<(), String> {
+        match self.named.keys().find(|name| !allowed.contains(&name.as_str())) {
+            Some(name) => Err(format!("unknown option --{}", name)),
+            None => Ok(()),
+        }
+    }
+
+    fn paths(&self) -> Result<(&str, &str), String> {
+        match self.positional.as_slice() {
+            [input, output] => Ok((input, output)),
+            _ => Err("expected an input and an output path".into()),
+        }
+    }
+}
+
+fn run_analyze(options: &Options) -> Result<(), Box<dyn Error>> {
+    options.check_names(&["format", "fft-size", "hop", "peaks"])?;
+    let defaults = AnalysisSettings::default();
+    let settings = AnalysisSettings {
+        fft_size: options.get("fft-size", defaults.fft_size)?,
+        hop_size: options.get("hop", defaults.hop_size)?,
+        max_peaks: options.get("peaks", defaults.max_peaks)?,
+    };
+    if !settings.fft_size.is_power_of_two() || settings.fft_size < 16 {
+        return Err("--fft-size must be a power of 2 of at least 16".into());
+    }
+    if settings.hop_size == 0 {
+        return Err("--hop must be at least 1".into());
+    }
+    if settings.max_peaks == 0 || settings.max_peaks > MAX_PEAKS {
+        return Err(format!("--peaks must be between 1 and {}", MAX_PEAKS).into());
+    }
+    let (input, output) = options.paths()?;
+    let default_format = match output.rsplit_once('.') {
+        Some((_, "sdif")) => "sdif",
+        Some((_, "csv")) => "csv",
+        Some((_, "json" | "jsonl")) => "json",
+        _ => "spear",
+    };
+    let format: String = options.get("format", default_format.to_string())?;
+    if !["sdif", "spear", "csv", "json"].contains(&format.as_str()) {
+        return Err(format!("unknown format {}", format).into());
+    }
+    if format == "json" && cfg!(not(feature = "serde")) {
+        return Err("json output needs the serde feature".into());
+    }
+
+    let (channels, sample_rate) = wav::read(input)?;
+    let samples = wav::mix_to_mono(&channels);
+    let frames = analyze(&samples, sample_rate, &settings);
+    let writer = BufWriter::new(File::create(output)?);
+    match format.as_str() {
+        "sdif" => sdif::write_frames(&frames, writer)?,
+        "csv" => csv::write_frames(&frames, writer)?,
+        #[cfg(feature = "serde")]
+        "json" => json::write_frames(&frames, writer)?,
+        _ => spear::write_frames(&frames, writer)?,
+    }
+    Ok(())
+}
+
+fn run_resynth(options: &Options) -> Result<(), Box<dyn Error>> {
+    options.check_names(&[
+        "transpose",
+        "preserve-formants",
+        "formant-shift",
+        "shift",
+        "stretch",
+        "pivot",
+        "harmonics",
+        "band",
+        "band-low",
+        "band-high",
+        "tilt",
+        "filter-depth",
+        "frequency-glide",
+        "amplitude-glide",
+        "amplitude-curve",
+        "waveform",
+        "quantize",
+        "root",
+        "kbm",
+        "quantize-amount",
+        "detune",
+        "drift-rate",
+        "noise",
+        "transients",
+        "freeze-at",
+        "partials",
+        "pan",
+        "width",
+        "mix",
+        "gain",
+        "limiter",
+        "block-size",
+        "seed",
+    ])?;
+    let defaults = RenderSettings::default();
+    let freeze_at = match options.named.get("freeze-at") {
+        Some(_) => Some(options.get("freeze-at", 0.0)?),
+        None => None,
+    };
+    let glide = |name| match options.named.get(name) {
+        Some(_) => options.get(name, 0.0).map(|ms: f32| Some(ms / 1000.0)),
+        None => Ok(None),
+    };
+    let amplitude_curve = match options.get("amplitude-curve", "linear".to_string())?.as_str() {
+        "linear" => SmoothingCurve::Linear,
+        "exponential" => SmoothingCurve::Exponential,
+        "db" => SmoothingCurve::Decibels,
+        curve => return Err(format!("unknown amplitude curve {}", curve).into()),
+    };
+    let (waveform, wavetable) = match options.get("waveform", "sine".to_string())?.as_str() {
+        "sine" => (Waveform::Sine, None),
+        "saw" => (Waveform::Saw, None),
+        "square" => (Waveform::Square, None),
+        "triangle" => (Waveform::Triangle, None),
+        path => {
+            let (channels, _) = wav::read(path)?;
+            (Waveform::Wavetable, Some(wav::mix_to_mono(&channels)))
+        }
+    };
+    let pan_mode = match options.get("pan", "index".to_string())?.as_str() {
+        "index" => PanMode::Index,
+        "frequency" => PanMode::Frequency,
+        "random" => PanMode::Random,
+        "alternating" => PanMode::Alternating,
+        mode => return Err(format!("unknown pan mode {}", mode).into()),
+    };
+    let filter_defaults = PartialFilter::default();
+    let partial_filter = PartialFilter {
+        harmonics: match options.get("harmonics", "all".to_string())?.as_str() {
+            "all" => Harmonics::All,
+            "odd" => Harmonics::Odd,
+            "even" => Harmonics::Even,
+            which => return Err(format!("unknown harmonics {}", which).into()),
+        },
+        band: match options.get("band", "off".to_string())?.as_str() {
+            "off" => Band::Off,
+            "inside" => Band::Inside,
+            "outside" => Band::Outside,
+            mode => return Err(format!("unknown band mode {}", mode).into()),
+        },
+        band_low: options.get("band-low", filter_defaults.band_low)?,
+        band_high: options.get("band-high", filter_defaults.band_high)?,
+        tilt: options.get("tilt", filter_defaults.tilt)?,
+        depth: options.get("filter-depth", filter_defaults.depth)?,
+        ..filter_defaults
+    };
+    let root = options.get("root", 261.63)?;
+    if root <= 0.0 {
+        return Err("--root must be above 0".into());
+    }
+    let scale = |kind| Quantize::Scale(Scale::new(kind, root));
+    let quantize = match options.get("quantize", "off".to_string())?.as_str() {
+        "off" => Quantize::Off,
+        "chromatic" => scale(ScaleKind::Chromatic),
+        "major" => scale(ScaleKind::Major),
+        "minor" => scale(ScaleKind::Minor),
+        "pentatonic" => scale(ScaleKind::Pentatonic),
+        "harmonics" => Quantize::Harmonics(root),
+        scl if scl.ends_with(".scl") => {
+            let tuning = Tuning::load(scl, options.named.get("kbm").map(String::as_str))
+                .map_err(|error| format!("can't load {}: {}", scl, error))?;
+            tuning
+                .scale()
+                .map(Quantize::Scale)
+                .ok_or("--quantize needs between 1 and 128 pitches")?
+        }
+        cents => {
+            let cents = cents
+                .split(',')
+                .map(|value| value.trim().parse::<f32>())
+                .collect::<Result<Vec<f32>, _>>()
+                .map_err(|_| format!("invalid value for --quantize: {}", cents))?;
+            Scale::from_cents(&cents, 1200.0, root)
+                .map(Quantize::Scale)
+                .ok_or("--quantize needs between 1 and 128 pitches")?
+        }
+    };
+    let limiter_mode = match options.get("limiter", "hard".to_string())?.as_str() {
+        "hard" => LimiterMode::HardClip,
+        "soft" => LimiterMode::SoftClip,
+        "lookahead" => LimiterMode::Lookahead,
+        mode => return Err(format!("unknown limiter mode {}", mode).into()),
+    };
+    let settings = RenderSettings {
+        transpose: options.get("transpose", defaults.transpose)?,
+        formant_preserve: options.get("preserve-formants", defaults.formant_preserve)?,
+        formant_shift: options.get("formant-shift", defaults.formant_shift)?,
+        frequency_shift: options.get("shift", defaults.frequency_shift)?,
+        stretch: options.get("stretch", defaults.stretch)?,
+        stretch_pivot: options.get("pivot", defaults.stretch_pivot)?,
+        partial_filter,
+        frequency_glide: glide("frequency-glide")?,
+        amplitude_glide: glide("amplitude-glide")?,
+        amplitude_curve,
+        waveform,
+        wavetable,
+        quantize,
+        quantize_amount: options.get("quantize-amount", defaults.quantize_amount)?,
+        detune: options.get("detune", defaults.detune)?,
+        drift_rate: options.get("drift-rate", defaults.drift_rate)?,
+        noise_level: options.get("noise", defaults.noise_level)?,
+        transient_mix: options.get("transients", defaults.transient_mix)?,
+        freeze_at,
+        partial_count: options.get("partials", defaults.partial_count)?,
+        pan_mode,
+        width: options.get("width", defaults.width)?,
+        mix: options.get("mix", defaults.mix)?,
+        output_gain: options.get("gain", defaults.output_gain)?,
+        limiter_mode,
+        block_size: options.get("block-size", defaults.block_size)?,
+        seed: options.get("seed", defaults.seed)?,
+    };
+    if settings.partial_count == 0 || settings.partial_count > MAX_PEAKS {
+        return Err(format!("--partials must be between 1 and {}", MAX_PEAKS).into());
+    }
+    if settings.block_size == 0 {
+        return Err("--block-size must be at least 1".into());
+    }
+    let (input, output) = options.paths()?;
+
+    let (channels, sample_rate) = wav::read(input)?;
+    let samples = wav::mix_to_mono(&channels);
+    let rendered = render(&samples, sample_rate, &settings);
+    wav::write(output, &rendered, sample_rate)?;
+    Ok(())
+}
+
+fn run(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let (command, args) = args.split_first().ok_or("missing command")?;
+    let options = Options::parse(args)?;
+    match command.as_str() {
+        "analyze" => run_analyze(&options),
+        "resynth" => run_resynth(&options),
+        _ => Err(format!("unknown command {}", command).into()),
+    }
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.is_empty() || args[0] == "help" || args[0] == "--help" {
+        println!("{}", USAGE);
+        return ExitCode::SUCCESS;
+    }
+    match run(&args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("error: {}", error);
+            eprintln!("{}", USAGE);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,134 @@
+use crate::drift;
+use crate::smooth::SmoothedValue;
+use std::f32::consts::PI;
+
+pub const BARK_BANDS: usize = 24;
+
+/// The edges of the critical bands of hearing in Hz.
+pub const BARK_EDGES: [f32; BARK_BANDS + 1] = [
+    0.0, 100.0, 200.0, 300.0, 400.0, 510.0, 630.0, 770.0, 920.0, 1080.0, 1270.0, 1480.0, 1720.0,
+    2000.0, 2320.0, 2700.0, 3150.0, 3700.0, 4400.0, 5300.0, 6400.0, 7700.0, 9500.0, 12000.0,
+    15500.0,
+];
+
+// Band levels glide over this many samples
+const LEVEL_SMOOTH_LENGTH: usize = 512;
+
+/// A bandpass filter that passes as much white noise power as an ideal
+/// filter of the same bandwidth would.
+#[derive(Debug, Default)]
+struct Bandpass {
+    b0: f32,
+    a1: f32,
+    a2: f32,
+    x1: f32,
+    x2: f32,
+    y1: f32,
+    y2: f32,
+}
+
+impl Bandpass {
+    fn new(low: f32, high: f32, sample_rate: f32) -> Self {
+        let nyquist = sample_rate * 0.5;
+        if low >= nyquist {
+            return Self::default();
+        }
+        let high = high.min(nyquist * 0.95);
+        let center = (low.max(20.0) * high).sqrt();
+        let q = center / (high - low);
+        let w0 = 2.0 * PI * center / sample_rate;
+        let alpha = w0.sin() / (2.0 * q);
+        let a0 = 1.0 + alpha;
+        // The noise bandwidth of a biquad bandpass is π/2 times its -3 dB
+        // bandwidth
+        let gain = (2.0 / PI).sqrt();
+        Self {
+            b0: gain * alpha / a0,
+            a1: -2.0 * w0.cos() / a0,
+            a2: (1.0 - alpha) / a0,
+            ..Self::default()
+        }
+    }
+
+    fn process(&mut self, x: f32) -> f32 {
+        // b1 is 0 and b2 is -b0
+        let y = self.b0 * (x - self.x2) - self.a1 * self.y1 - self.a2 * self.y2;
+        self.x2 = self.x1;
+        self.x1 = x;
+        self.y2 = self.y1;
+        self.y1 = y;
+        y
+    }
+}
+
+/// White noise through a bank of Bark band filters, each with its own
+/// level. A level is the standard deviation of white noise that would have
+/// the same power in that band.
+pub struct NoiseBands {
+    filters: [Bandpass; BARK_BANDS],
+    levels: [SmoothedValue; BARK_BANDS],
+    seed: u64,
+}
+
+impl NoiseBands {
+    pub fn new(sample_rate: f32, seed: u64) -> Self {
+        Self {
+            filters: std::array::from_fn(|band| {
+                Bandpass::new(BARK_EDGES[band], BARK_EDGES[band + 1], sample_rate)
+            }),
+            levels: std::array::from_fn(|_| SmoothedValue::new(0.0, LEVEL_SMOOTH_LENGTH)),
+            seed,
+        }
+    }
+
+    pub fn set_levels(&mut self, levels: &[f32; BARK_BANDS]) {
+        for (smoother, level) in self.levels.iter_mut().zip(levels.iter()) {
+            smoother.set_target(*level);
+        }
+    }
+
+    /// Adds the noise to both channels with the given gains.
+    pub fn render_block(&mut self, left: &mut [f32], right: &mut [f32], gains: (f32, f32)) {
+        // Uniform noise between -1 and 1 has a standard deviation of 1/√3
+        let scale = 3_f32.sqrt();
+        for (left, right) in left.iter_mut().zip(right.iter_mut()) {
+            let white = drift::random(self.seed) * scale;
+            self.seed = self.seed.wrapping_add(1);
+            let mut sample = 0.0;
+            for (filter, level) in self.filters.iter_mut().zip(self.levels.iter_mut()) {
+                sample += filter.process(white) * level.next();
+            }
+            *left += sample * gains.0;
+            *right += sample * gains.1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn rms(samples: &[f32]) -> f32 {
+        (samples.iter().map(|x| x * x).sum::<f32>() / samples.len() as f32).sqrt()
+    }
+
+    #[test]
+    fn test_noise_bands() {
+        let mut noise = NoiseBands::new(48000.0, 1);
+        let (mut left, mut right) = (vec![0_f32; 48000], vec![0_f32; 48000]);
+        noise.render_block(&mut left, &mut right, (1.0, 0.5));
+        assert!(left.iter().all(|x| *x == 0.0));
+
+        // One band at a level of 0.5 has the power of white noise with a
+        // standard deviation of 0.5 in that band
+        let mut levels = [0.0; BARK_BANDS];
+        levels[10] = 0.5;
+        noise.set_levels(&levels);
+        noise.render_block(&mut left, &mut right, (1.0, 0.5));
+        let bandwidth = BARK_EDGES[11] - BARK_EDGES[10];
+        let expected = 0.5 * (bandwidth / 24000.0).sqrt();
+        let measured = rms(&left[LEVEL_SMOOTH_LENGTH..]);
+        assert!((measured / expected - 1.0).abs() < 0.3, "{} {}", measured, expected);
+        assert!((rms(&right[LEVEL_SMOOTH_LENGTH..]) - measured * 0.5).abs() < 1e-4);
+    }
+}
@@ -1,13 +1,153 @@
+use crate::utils::wrap_phase;
+use realfft::RealFftPlanner;
 use std::f32::consts::PI;
+use std::sync::Arc;
 
+const TABLE_SIZE: usize = 2048;
+// Each table level keeps half the harmonics of the one before, down to the
+// fundamental alone
+const TABLE_LEVELS: usize = 11;
+
+/// The shape of each partial.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Waveform {
+    Sine,
+    Saw,
+    Square,
+    Triangle,
+    /// The oscillator's wavetable, or a sine without one
+    Wavetable,
+}
+
+/// A single cycle kept at several band limits, so that no harmonic of a
+/// partial goes above Nyquist.
 #[derive(Debug)]
-pub struct SinOsc {
+pub struct Wavetable {
+    levels: Vec<Vec<f32>>,
+}
+
+impl Wavetable {
+    /// The cycle can have any length, it is resampled to the table size and
+    /// normalized. `None` if it is silent.
+    pub fn new(cycle: &[f32]) -> Option<Self> {
+        if cycle.is_empty() {
+            return None;
+        }
+        let mut resampled: Vec<f32> = (0..TABLE_SIZE)
+            .map(|index| {
+                let position = index as f32 * cycle.len() as f32 / TABLE_SIZE as f32;
+                let before = cycle[position as usize];
+                let after = cycle[(position as usize + 1) % cycle.len()];
+                before + (after - before) * position.fract()
+            })
+            .collect();
+        let mut planner = RealFftPlanner::<f32>::new();
+        let forward = planner.plan_fft_forward(TABLE_SIZE);
+        let inverse = planner.plan_fft_inverse(TABLE_SIZE);
+        let mut spectrum = forward.make_output_vec();
+        forward.process(&mut resampled, &mut spectrum).ok()?;
+        // Without the DC offset
+        spectrum[0].re = 0.0;
+        let levels = (0..TABLE_LEVELS)
+            .map(|level| {
+                let harmonics = (TABLE_SIZE / 2) >> level;
+                let mut limited = spectrum.clone();
+                for bin in limited.iter_mut().skip(harmonics + 1) {
+                    bin.re = 0.0;
+                    bin.im = 0.0;
+                }
+                let mut table = inverse.make_output_vec();
+                inverse.process(&mut limited, &mut table).ok()?;
+                Some(table)
+            })
+            .collect::<Option<Vec<Vec<f32>>>>()?;
+        let peak = levels[0].iter().fold(0_f32, |peak, x| peak.max(x.abs()));
+        if peak <= 0.0 {
+            return None;
+        }
+        let levels = levels
+            .into_iter()
+            .map(|table| table.iter().map(|x| x / peak).collect())
+            .collect();
+        Some(Self { levels })
+    }
+
+    /// The value at `phase` between 0 and 1 of the most detailed level whose
+    /// harmonics all stay below Nyquist at `frequency` in cycles per sample.
+    fn sample(&self, phase: f32, frequency: f32) -> f32 {
+        let allowed = (0.5 / frequency.max(f32::EPSILON)) as usize;
+        let level = (0..TABLE_LEVELS)
+            .find(|level| (TABLE_SIZE / 2) >> level <= allowed)
+            .unwrap_or(TABLE_LEVELS - 1);
+        let table = &self.levels[level];
+        let position = phase * TABLE_SIZE as f32;
+        let index = position as usize % TABLE_SIZE;
+        let next = table[(index + 1) % TABLE_SIZE];
+        table[index] + (next - table[index]) * position.fract()
+    }
+}
+
+/// Smooths a jump of 2 at phase 0 over the samples either side of it, where
+/// `t` is the phase and `dt` the frequency, both in cycles (PolyBLEP).
+fn poly_blep(t: f32, dt: f32) -> f32 {
+    if t < dt {
+        let x = t / dt;
+        2.0 * x - x * x - 1.0
+    } else if t > 1.0 - dt {
+        let x = (t - 1.0) / dt;
+        x * x + 2.0 * x + 1.0
+    } else {
+        0.0
+    }
+}
+
+/// The integral of `poly_blep`, which rounds off a kink instead (PolyBLAMP).
+fn poly_blamp(t: f32, dt: f32) -> f32 {
+    if t < dt {
+        let x = t / dt - 1.0;
+        -x * x * x / 3.0
+    } else if t > 1.0 - dt {
+        let x = (t - 1.0) / dt + 1.0;
+        x * x * x / 3.0
+    } else {
+        0.0
+    }
+}
+
+/// A cubic phase track (McAulay-Quatieri) that starts at the oscillator's
+/// phase and frequency and ends at a target phase and frequency, without a
+/// jump in either.
+#[derive(Debug)]
+struct PhaseTrack {
+    start_phase: f32,
+    start_frequency: f32,
+    alpha: f32,
+    beta: f32,
+    end_phase: f32,
+    position: usize,
+    length: usize,
+}
+
+impl PhaseTrack {
+    fn phase_at(&self, t: f32) -> f32 {
+        self.start_phase + t * (self.start_frequency + t * (self.alpha + t * self.beta))
+    }
+}
+
+#[derive(Debug)]
+pub struct Osc {
     frequency: f32, // radians per sample
     amplitude: f32,
     phase: f32,
     lowpass_amp: f32, // multiplier to prevent aliasing
+    track: Option<PhaseTrack>,
+    waveform: Waveform,
+    wavetable: Option<Arc<Wavetable>>,
 }
 
+#[deprecated(note = "renamed to `Osc` since it plays more than sines")]
+pub type SinOsc = Osc;
+
 fn get_lowpass_amp(hz: f32) -> f32 {
     if hz > 18000.0 {
         (-0.00025 * hz + 5.5).clamp(0.0, 1.0)
@@ -16,35 +156,135 @@ fn get_lowpass_amp(hz: f32) -> f32 {
     }
 }
 
-impl SinOsc {
+impl Osc {
     pub fn new(frequency: f32, amplitude: f32, phase: f32) -> Self {
         Self {
             frequency,
             amplitude,
             phase,
             lowpass_amp: 1.0,
+            track: None,
+            waveform: Waveform::Sine,
+            wavetable: None,
+        }
+    }
+
+    pub fn set_waveform(&mut self, waveform: Waveform) {
+        self.waveform = waveform;
+    }
+
+    /// Not for the audio thread, dropping the last reference to the previous
+    /// table deallocates it.
+    pub fn set_wavetable(&mut self, wavetable: Option<Arc<Wavetable>>) {
+        self.wavetable = wavetable;
+    }
+
+    /// The waveform at `phase` in radians. The discontinuities are smoothed
+    /// according to the frequency so they don't alias.
+    fn shape(&self, phase: f32) -> f32 {
+        let t = (phase / (2.0 * PI)).rem_euclid(1.0);
+        let dt = self.frequency / (2.0 * PI);
+        let half = (t + 0.5) % 1.0;
+        match (self.waveform, &self.wavetable) {
+            (Waveform::Saw, _) => 2.0 * t - 1.0 - poly_blep(t, dt),
+            (Waveform::Square, _) => {
+                let naive = if t < 0.5 { 1.0 } else { -1.0 };
+                naive + poly_blep(t, dt) - poly_blep(half, dt)
+            }
+            (Waveform::Triangle, _) => {
+                let naive = 1.0 - 4.0 * (t - 0.5).abs();
+                naive + 4.0 * dt * (poly_blamp(t, dt) - poly_blamp(half, dt))
+            }
+            (Waveform::Wavetable, Some(wavetable)) => wavetable.sample(t, dt),
+            (Waveform::Sine | Waveform::Wavetable, _) => f32::sin(phase),
         }
     }
 
     pub fn set_frequency_hz(&mut self, hz: f32, sample_rate: f32) {
+        if self.track.is_some() {
+            return;
+        }
         let hz = hz.clamp(20.0, sample_rate * 0.5);
         self.frequency = 2.0 * PI * hz / sample_rate;
         self.lowpass_amp = get_lowpass_amp(hz);
     }
 
+    /// Moves to `hz` over the next `samples` samples so that the phase after
+    /// them is `phase`, taking whichever number of extra cycles makes for the
+    /// smoothest frequency glide. Until then `set_frequency_hz` has no effect.
+    pub fn set_phase_target(&mut self, hz: f32, phase: f32, samples: usize, sample_rate: f32) {
+        let start_frequency = self.frequency;
+        self.track = None;
+        self.set_frequency_hz(hz, sample_rate);
+        if samples == 0 {
+            self.phase = wrap_phase(phase);
+            self.track = None;
+            return;
+        }
+        let glide = self.frequency - start_frequency;
+        let t = samples as f32;
+        let predicted = self.phase + start_frequency * t;
+        let cycles = ((predicted - phase + glide * t * 0.5) / (2.0 * PI)).round();
+        let error = phase + cycles * 2.0 * PI - predicted;
+        self.track = Some(PhaseTrack {
+            start_phase: self.phase,
+            start_frequency,
+            alpha: 3.0 * error / (t * t) - glide / t,
+            beta: -2.0 * error / (t * t * t) + glide / (t * t),
+            end_phase: wrap_phase(phase),
+            position: 0,
+            length: samples,
+        });
+    }
+
     pub fn set_amplitude(&mut self, amplitude: f32) {
         self.amplitude = amplitude;
     }
 
+    /// In radians per sample.
+    pub fn frequency(&self) -> f32 {
+        self.frequency
+    }
+
+    /// The amplitude with the anti-aliasing fade.
+    pub fn gain(&self) -> f32 {
+        self.amplitude * self.lowpass_amp
+    }
+
+    pub fn phase(&self) -> f32 {
+        self.phase
+    }
+
+    /// Moves the phase, e.g. after running in a `SineBank`. Not while
+    /// following a phase target.
+    pub fn set_phase(&mut self, phase: f32) {
+        self.phase = phase;
+    }
+
+    pub fn is_tracking(&self) -> bool {
+        self.track.is_some()
+    }
+
     #[allow(clippy::should_implement_trait)]
     pub fn next(&mut self) -> f32 {
         let phase = self.phase;
-        self.phase = (self.phase + self.frequency) % (2.0 * PI);
-        f32::sin(phase) * self.amplitude * self.lowpass_amp
+        match &mut self.track {
+            Some(track) => {
+                track.position += 1;
+                if track.position == track.length {
+                    self.phase = track.end_phase;
+                    self.track = None;
+                } else {
+                    self.phase = track.phase_at(track.position as f32);
+                }
+            }
+            None => self.phase = (self.phase + self.frequency) % (2.0 * PI),
+        }
+        self.shape(phase) * self.amplitude * self.lowpass_amp
     }
 
     pub fn current_value(&self) -> f32 {
-        f32::sin(self.phase) * self.amplitude
+        self.shape(self.phase) * self.amplitude
     }
 }
 
@@ -54,7 +294,7 @@ mod test {
 
     #[test]
     fn test_osc() {
-        let mut osc = SinOsc::new(0.25 * PI, 1.0, 0.0);
+        let mut osc = Osc::new(0.25 * PI, 1.0, 0.0);
         assert!(osc.next().abs() < 0.0001);
         assert!((osc.next() - std::f32::consts::FRAC_1_SQRT_2).abs() < 0.0001);
         assert!((osc.next() - 1.0).abs() < 0.0001);
@@ -70,4 +310,56 @@ mod test {
         osc.set_frequency_hz(12000.0, 48000.0);
         assert!((osc.frequency - PI / 2.0).abs() < 0.00001);
     }
+
+    #[test]
+    fn test_phase_target() {
+        let mut osc = Osc::new(0.0, 1.0, 0.0);
+        osc.set_frequency_hz(440.0, 48000.0);
+        for _ in 0..100 {
+            osc.next();
+        }
+        osc.set_phase_target(450.0, 1.0, 256, 48000.0);
+        let samples: Vec<f32> = (0..256).map(|_| osc.next()).collect();
+        // The glide has no jumps: at most a step of about 450 Hz per sample
+        let step = 2.0 * PI * 450.0 / 48000.0;
+        assert!(samples.windows(2).all(|pair| (pair[1] - pair[0]).abs() < step * 1.1));
+        assert!((osc.next() - 1_f32.sin()).abs() < 1e-3);
+        // and it runs freely at the new frequency afterwards
+        assert!((osc.next() - (1.0 + step).sin()).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_waveforms() {
+        let cycle: Vec<f32> = (0..100).map(|i| (2.0 * PI * i as f32 / 100.0).sin()).collect();
+        let wavetable = Arc::new(Wavetable::new(&cycle).unwrap());
+        assert!(Wavetable::new(&[0.0; 16]).is_none());
+        for waveform in [Waveform::Saw, Waveform::Square, Waveform::Triangle, Waveform::Wavetable] {
+            let mut osc = Osc::new(0.0, 1.0, 0.0);
+            osc.set_waveform(waveform);
+            osc.set_wavetable(Some(wavetable.clone()));
+            osc.set_frequency_hz(1000.0, 48000.0);
+            let samples: Vec<f32> = (0..4800).map(|_| osc.next()).collect();
+            assert!(samples.iter().all(|x| x.abs() <= 1.01), "{:?}", waveform);
+            let crossings = samples.windows(2).filter(|pair| pair[0] < 0.0 && pair[1] >= 0.0);
+            // A square rises at the very start, before the first sample
+            assert!((99..=100).contains(&crossings.count()), "{:?}", waveform);
+        }
+
+        // The table of a sine cycle plays a sine
+        let mut osc = Osc::new(0.0, 1.0, 0.0);
+        osc.set_waveform(Waveform::Wavetable);
+        osc.set_wavetable(Some(wavetable));
+        osc.set_frequency_hz(440.0, 48000.0);
+        let step = 2.0 * PI * 440.0 / 48000.0;
+        for index in 0..1000 {
+            assert!((osc.next() - (index as f32 * step).sin()).abs() < 1e-3);
+        }
+
+        // Near Nyquist a saw's corrections cover its whole jump
+        let mut saw = Osc::new(0.0, 1.0, 0.0);
+        saw.set_waveform(Waveform::Saw);
+        saw.set_frequency_hz(12000.0, 48000.0);
+        let samples: Vec<f32> = (0..8).map(|_| saw.next()).collect();
+        assert!(samples.windows(2).all(|pair| (pair[1] - pair[0]).abs() < 1.5));
+    }
 }
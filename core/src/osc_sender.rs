@@ -0,0 +1,158 @@
+use crate::peak::{Peak, MAX_PEAKS};
+use crossbeam_queue::ArrayQueue;
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+const QUEUE_LENGTH: usize = 64;
+const POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// The tracked peaks of one frame with the id of the track in each slot.
+#[derive(Debug, Clone, Copy)]
+pub struct PeakFrame {
+    pub peaks: [Option<Peak>; MAX_PEAKS],
+    pub track_ids: [usize; MAX_PEAKS],
+}
+
+/// Frames of tracked peaks handed from the audio thread to the sender thread.
+/// Pushing never allocates, so it is safe to call from `Reconstructor::run`.
+pub type PeakQueue = Arc<ArrayQueue<PeakFrame>>;
+
+fn write_padded_string(packet: &mut Vec<u8>, value: &str) {
+    packet.extend_from_slice(value.as_bytes());
+    let padding = 4 - value.len() % 4;
+    packet.resize(packet.len() + padding, 0);
+}
+
+/// Encodes one frame as an OSC bundle holding a `/peaks` message of
+/// (track id, frequency, amplitude) for every active track. A track keeps its
+/// id for as long as it lasts, while its slot is reused for later tracks.
+fn encode_bundle(frame: &PeakFrame, packet: &mut Vec<u8>) {
+    packet.clear();
+    write_padded_string(packet, "#bundle");
+    // Time tag 1 means "immediately"
+    packet.extend_from_slice(&1_u64.to_be_bytes());
+    for (peak, track) in frame.peaks.iter().zip(frame.track_ids.iter()) {
+        if let Some(peak) = peak {
+            let size_index = packet.len();
+            packet.extend_from_slice(&[0; 4]);
+            write_padded_string(packet, "/peaks");
+            write_padded_string(packet, ",iff");
+            packet.extend_from_slice(&(*track as i32).to_be_bytes());
+            packet.extend_from_slice(&peak.frequency.to_be_bytes());
+            packet.extend_from_slice(&peak.amplitude.to_be_bytes());
+            let size = (packet.len() - size_index - 4) as i32;
+            packet[size_index..size_index + 4].copy_from_slice(&size.to_be_bytes());
+        }
+    }
+}
+
+/// Sends the frames pushed onto its queue as OSC bundles over UDP from a
+/// background thread. The thread is stopped when the sender is dropped.
+pub struct OscSender {
+    queue: PeakQueue,
+    running: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl OscSender {
+    pub fn spawn<A: ToSocketAddrs>(target: A) -> io::Result<Self> {
+        let target: SocketAddr = target
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no target address"))?;
+        let bind_address: SocketAddr = if target.is_ipv4() {
+            ([0, 0, 0, 0], 0).into()
+        } else {
+            ([0_u16; 8], 0).into()
+        };
+        let socket = UdpSocket::bind(bind_address)?;
+        socket.connect(target)?;
+
+        let queue: PeakQueue = Arc::new(ArrayQueue::new(QUEUE_LENGTH));
+        let running = Arc::new(AtomicBool::new(true));
+        let thread = {
+            let queue = queue.clone();
+            let running = running.clone();
+            std::thread::Builder::new()
+                .name("osc-sender".into())
+                .spawn(move || {
+                    let mut packet = Vec::with_capacity(1024);
+                    while running.load(Ordering::Relaxed) {
+                        while let Some(frame) = queue.pop() {
+                            encode_bundle(&frame, &mut packet);
+                            // Nobody listening is not an error worth stopping for
+                            let _ = socket.send(&packet);
+                        }
+                        std::thread::sleep(POLL_INTERVAL);
+                    }
+                })?
+        };
+        Ok(Self {
+            queue,
+            running,
+            thread: Some(thread),
+        })
+    }
+
+    pub fn queue(&self) -> PeakQueue {
+        self.queue.clone()
+    }
+}
+
+impl Drop for OscSender {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn read_i32(packet: &[u8], index: usize) -> i32 {
+        i32::from_be_bytes(packet[index..index + 4].try_into().unwrap())
+    }
+
+    fn read_f32(packet: &[u8], index: usize) -> f32 {
+        f32::from_be_bytes(packet[index..index + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn test_send_peaks() {
+        let listener = UdpSocket::bind("127.0.0.1:0").unwrap();
+        listener
+            .set_read_timeout(Some(Duration::from_secs(2)))
+            .unwrap();
+        let sender = OscSender::spawn(listener.local_addr().unwrap()).unwrap();
+
+        let mut peaks = [None; MAX_PEAKS];
+        peaks[3] = Some(Peak {
+            frequency: 440.0,
+            amplitude: 0.5,
+            phase: 0.0,
+        });
+        let mut track_ids = [0; MAX_PEAKS];
+        track_ids[3] = 17;
+        sender.queue().force_push(PeakFrame { peaks, track_ids });
+
+        let mut packet = [0_u8; 1024];
+        let length = listener.recv(&mut packet).unwrap();
+        let packet = &packet[0..length];
+        assert_eq!(&packet[0..8], b"#bundle\0");
+        assert_eq!(read_i32(packet, 16), 28);
+        assert_eq!(&packet[20..28], b"/peaks\0\0");
+        assert_eq!(&packet[28..36], b",iff\0\0\0\0");
+        // The track's id, not its slot
+        assert_eq!(read_i32(packet, 36), 17);
+        assert_eq!(read_f32(packet, 40), 440.0);
+        assert_eq!(read_f32(packet, 44), 0.5);
+        assert_eq!(length, 48);
+    }
+}
@@ -0,0 +1,63 @@
+use std::f32::consts::FRAC_PI_4;
+
+/// How the partials are spread across the stereo field.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PanMode {
+    /// From left to right by oscillator slot
+    Index,
+    /// Low partials to the left, high partials to the right
+    Frequency,
+    /// A fixed random position per oscillator
+    Random,
+    /// Alternating left and right by slot
+    Alternating,
+}
+
+// The frequency range spread from left to right in `PanMode::Frequency`
+const LOW_FREQUENCY: f32 = 50.0;
+const HIGH_FREQUENCY: f32 = 5000.0;
+
+/// The pan position between -1 (left) and 1 (right) of the partial in `slot`
+/// out of `slots`. `random` is between -1 and 1.
+pub fn position(mode: PanMode, slot: usize, slots: usize, frequency: f32, random: f32) -> f32 {
+    match mode {
+        PanMode::Index if slots > 1 => slot as f32 / (slots - 1) as f32 * 2.0 - 1.0,
+        PanMode::Index => 0.0,
+        PanMode::Frequency => {
+            let octaves = (HIGH_FREQUENCY / LOW_FREQUENCY).log2();
+            ((frequency / LOW_FREQUENCY).log2() / octaves * 2.0 - 1.0).clamp(-1.0, 1.0)
+        }
+        PanMode::Random => random.clamp(-1.0, 1.0),
+        PanMode::Alternating => [-1.0, 1.0][slot % 2],
+    }
+}
+
+/// Equal power (left, right) gains, so a partial keeps its loudness wherever
+/// it is panned. In the centre both gains are 1/√2.
+pub fn gains(pan: f32) -> (f32, f32) {
+    let angle = (pan.clamp(-1.0, 1.0) + 1.0) * FRAC_PI_4;
+    (angle.cos(), angle.sin())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::f32::consts::FRAC_1_SQRT_2;
+
+    #[test]
+    fn test_pan() {
+        assert_eq!(position(PanMode::Index, 0, 20, 440.0, 0.0), -1.0);
+        assert_eq!(position(PanMode::Index, 19, 20, 440.0, 0.0), 1.0);
+        assert_eq!(position(PanMode::Frequency, 3, 20, 10.0, 0.0), -1.0);
+        assert!(position(PanMode::Frequency, 3, 20, 500.0, 0.0).abs() < 1e-6);
+        assert_eq!(position(PanMode::Random, 3, 20, 500.0, 0.25), 0.25);
+        assert_eq!(position(PanMode::Alternating, 3, 20, 500.0, 0.0), 1.0);
+
+        let (left, right) = gains(0.0);
+        assert!((left - FRAC_1_SQRT_2).abs() < 1e-6 && (right - FRAC_1_SQRT_2).abs() < 1e-6);
+        let (left, right) = gains(-1.0);
+        assert!((left - 1.0).abs() < 1e-6 && right.abs() < 1e-6);
+        let (left, right) = gains(0.3);
+        assert!((left * left + right * right - 1.0).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,210 @@
+use crate::cross::loudest;
+use crate::peak::{Peak, MAX_PEAKS};
+
+// Partials within this ratio of a multiple of the fundamental count as that
+// harmonic, about a quarter tone
+const HARMONIC_TOLERANCE: f32 = 0.03;
+const LOWEST_FUNDAMENTAL: f32 = 20.0;
+// The highest harmonic a partial is taken to be when looking for the
+// fundamental
+const MAX_DIVISOR: usize = 4;
+// A lower fundamental has to explain this much more of the spectrum to win,
+// as every subharmonic of the real one explains all of it too
+const LOWER_FUNDAMENTAL_MARGIN: f32 = 1.05;
+// Tilting leaves partials at this frequency as they are
+const TILT_PIVOT: f32 = 1000.0;
+
+/// Which harmonics of the detected fundamental pass.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Harmonics {
+    All,
+    /// The fundamental, the third harmonic, the fifth and so on
+    Odd,
+    Even,
+}
+
+/// Whether the partials inside or outside a frequency band pass.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Band {
+    Off,
+    Inside,
+    Outside,
+}
+
+/// Rules that drop or turn down partials before they are resynthesized.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PartialFilter {
+    /// Only this many of the loudest partials pass
+    pub loudest: usize,
+    pub harmonics: Harmonics,
+    pub band: Band,
+    /// In Hz
+    pub band_low: f32,
+    pub band_high: f32,
+    /// In dB per octave around 1 kHz
+    pub tilt: f32,
+    /// How far partials that don't pass are turned down, between 0 and 1
+    /// where they are dropped
+    pub depth: f32,
+}
+
+impl Default for PartialFilter {
+    /// Everything passes.
+    fn default() -> Self {
+        Self {
+            loudest: MAX_PEAKS,
+            harmonics: Harmonics::All,
+            band: Band::Off,
+            band_low: 200.0,
+            band_high: 2000.0,
+            tilt: 0.0,
+            depth: 1.0,
+        }
+    }
+}
+
+impl PartialFilter {
+    fn passes(&self, rank: usize, frequency: f32, fundamental: Option<f32>) -> bool {
+        let inside = frequency >= self.band_low && frequency <= self.band_high;
+        let harmonic = fundamental.map(|fundamental| (frequency / fundamental).round() as usize);
+        rank < self.loudest
+            && match self.band {
+                Band::Off => true,
+                Band::Inside => inside,
+                Band::Outside => !inside,
+            }
+            && match (self.harmonics, harmonic) {
+                (Harmonics::All, _) | (_, None) => true,
+                (Harmonics::Odd, Some(harmonic)) => harmonic & 1 == 1,
+                (Harmonics::Even, Some(harmonic)) => harmonic & 1 == 0,
+            }
+    }
+}
+
+/// How much of the total amplitude lies on multiples of `fundamental`.
+fn harmonicity(peaks: &[Option<Peak>; MAX_PEAKS], fundamental: f32) -> f32 {
+    peaks
+        .iter()
+        .flatten()
+        .filter(|peak| {
+            let harmonic = (peak.frequency / fundamental).round().max(1.0);
+            (peak.frequency / (harmonic * fundamental) - 1.0).abs() < HARMONIC_TOLERANCE
+        })
+        .map(|peak| peak.amplitude)
+        .sum()
+}
+
+/// Estimates the fundamental frequency by trying each partial as one of the
+/// first few harmonics and keeping the one that explains most of the
+/// spectrum.
+pub fn fundamental(peaks: &[Option<Peak>; MAX_PEAKS]) -> Option<f32> {
+    let mut best: Option<(f32, f32)> = None;
+    for divisor in 1..=MAX_DIVISOR {
+        for peak in peaks.iter().flatten() {
+            let candidate = peak.frequency / divisor as f32;
+            if candidate < LOWEST_FUNDAMENTAL {
+                continue;
+            }
+            let score = harmonicity(peaks, candidate);
+            best = match best {
+                Some((_, best_score)) if score <= best_score * LOWER_FUNDAMENTAL_MARGIN => best,
+                _ => Some((candidate, score)),
+            };
+        }
+    }
+    best.filter(|(_, score)| *score > 0.0)
+        .map(|(fundamental, _)| fundamental)
+}
+
+/// Turns down the partials that don't pass the filter's rules and tilts the
+/// spectrum.
+pub fn filter_partials(
+    peaks: &[Option<Peak>; MAX_PEAKS],
+    filter: &PartialFilter,
+) -> [Option<Peak>; MAX_PEAKS] {
+    if *filter == PartialFilter::default() {
+        return *peaks;
+    }
+    let fundamental = match filter.harmonics {
+        Harmonics::All => None,
+        _ => fundamental(peaks),
+    };
+    let (order, len) = loudest(peaks);
+    let depth = filter.depth.clamp(0.0, 1.0);
+    let mut filtered = *peaks;
+    for (rank, slot) in order[0..len].iter().enumerate() {
+        if let Some(peak) = &mut filtered[*slot] {
+            if !filter.passes(rank, peak.frequency, fundamental) {
+                if depth >= 1.0 {
+                    filtered[*slot] = None;
+                    continue;
+                }
+                peak.amplitude *= 1.0 - depth;
+            }
+            if filter.tilt != 0.0 && peak.frequency > 0.0 {
+                let octaves = (peak.frequency / TILT_PIVOT).log2();
+                peak.amplitude *= 10_f32.powf(filter.tilt * octaves / 20.0);
+            }
+        }
+    }
+    filtered
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn harmonic_peaks(fundamental: f32, count: usize) -> [Option<Peak>; MAX_PEAKS] {
+        let mut peaks = [None; MAX_PEAKS];
+        for (index, peak) in peaks.iter_mut().take(count).enumerate() {
+            *peak = Some(Peak {
+                frequency: fundamental * (index + 1) as f32,
+                amplitude: 1.0 / (index + 1) as f32,
+                phase: 0.0,
+            });
+        }
+        peaks
+    }
+
+    fn frequencies(peaks: &[Option<Peak>; MAX_PEAKS]) -> Vec<f32> {
+        peaks.iter().flatten().map(|peak| peak.frequency).collect()
+    }
+
+    #[test]
+    fn test_filter_partials() {
+        let peaks = harmonic_peaks(110.0, 8);
+        assert!((fundamental(&peaks).unwrap() - 110.0).abs() < 1e-3);
+        // A missing fundamental is still found from its harmonics
+        let mut missing = peaks;
+        missing[0] = None;
+        assert!((fundamental(&missing).unwrap() - 110.0).abs() < 1e-3);
+
+        assert_eq!(filter_partials(&peaks, &PartialFilter::default()), peaks);
+        let odd = PartialFilter {
+            harmonics: Harmonics::Odd,
+            ..PartialFilter::default()
+        };
+        assert_eq!(frequencies(&filter_partials(&peaks, &odd)), [110.0, 330.0, 550.0, 770.0]);
+
+        let band = PartialFilter {
+            band: Band::Outside,
+            band_low: 300.0,
+            band_high: 700.0,
+            loudest: 6,
+            ..PartialFilter::default()
+        };
+        assert_eq!(frequencies(&filter_partials(&peaks, &band)), [110.0, 220.0]);
+
+        // Turned down by half and tilted up by an octave's 6 dB
+        let tilted = PartialFilter {
+            harmonics: Harmonics::Even,
+            depth: 0.5,
+            tilt: 6.0,
+            ..PartialFilter::default()
+        };
+        let filtered = filter_partials(&harmonic_peaks(500.0, 2), &tilted);
+        let expected = 0.5 * 10_f32.powf(-6.0 / 20.0);
+        assert!((filtered[0].unwrap().amplitude - expected).abs() < 1e-4);
+        assert!((filtered[1].unwrap().amplitude - 0.5).abs() < 1e-4);
+    }
+}
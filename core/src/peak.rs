@@ -1,5 +1,12 @@
+pub const MAX_PEAKS: usize = 20;
+
 #[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Peak {
     pub frequency: f32,
     pub amplitude: f32,
+    /// In radians, of the partial as a sine at the centre of the analysis
+    /// window
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub phase: f32,
 }
@@ -0,0 +1,282 @@
+use crate::analysis::Frame;
+use crate::formats::sdif;
+use crate::limiter::{Limiter, LimiterMode, LOOKAHEAD};
+use crate::pan::PanMode;
+use crate::peak::{Peak, MAX_PEAKS};
+use crate::reconstructor::{headroom, FrequencyTransform, Glide, ReconstructorVoice};
+use crate::voice::{Event, Note, Synth, Voice};
+use assert_no_alloc::assert_no_alloc;
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::path::Path;
+use std::sync::Arc;
+
+/// Looks up the peaks at a time in seconds, interpolating the tracks that
+/// continue from one frame to the next.
+fn peaks_at(frames: &[Frame], time: f64) -> [Option<Peak>; MAX_PEAKS] {
+    let index = frames.partition_point(|frame| frame.time <= time);
+    if index == 0 || index == frames.len() {
+        return [None; MAX_PEAKS];
+    }
+    let (current, next) = (&frames[index - 1], &frames[index]);
+    let amount = ((time - current.time) / (next.time - current.time)) as f32;
+    let mut peaks = current.peaks;
+    for (slot, peak) in peaks.iter_mut().enumerate() {
+        if let (Some(peak), Some(next_peak)) = (peak, next.peaks[slot]) {
+            if current.track_ids[slot] == next.track_ids[slot] {
+                peak.frequency += (next_peak.frequency - peak.frequency) * amount;
+                peak.amplitude += (next_peak.amplitude - peak.amplitude) * amount;
+            }
+        }
+    }
+    peaks
+}
+
+#[derive(Clone, Copy)]
+struct PlaybackSettings {
+    speed: f64,
+    loop_region: Option<(f64, f64)>,
+}
+
+/// A voice with its own playhead into the partial tracks. The playhead
+/// restarts with every note.
+pub struct PlaybackVoice {
+    voice: ReconstructorVoice,
+    frames: Arc<Vec<Frame>>,
+    settings: PlaybackSettings,
+    position: f64,
+}
+
+impl Voice for PlaybackVoice {
+    fn get_note(&self) -> &Option<Note> {
+        self.voice.get_note()
+    }
+
+    fn set_note(&mut self, note: Option<Note>) {
+        self.voice.set_note(note);
+    }
+
+    fn note_on(&mut self, note_number: u8, velocity: u8) {
+        self.position = match self.settings.loop_region {
+            Some((start, _)) => start,
+            None => 0.0,
+        };
+        self.voice.note_on(note_number, velocity);
+    }
+
+    fn render_block(&mut self, left: &mut [f32], right: &mut [f32]) {
+        if self.is_free() {
+            return self.voice.render_block(left, right);
+        }
+        let peaks = peaks_at(&self.frames, self.position);
+        self.voice
+            .prepare_oscillators(&peaks, FrequencyTransform::default(), Glide::default());
+        self.voice.render_block(left, right);
+
+        self.position += left.len() as f64 * self.settings.speed / self.voice.sample_rate as f64;
+        if let Some((start, end)) = self.settings.loop_region {
+            if self.position >= end {
+                self.position = start + (self.position - start) % (end - start);
+            }
+        }
+    }
+}
+
+/// Plays partial tracks from a file through the resynthesis oscillator banks,
+/// like a sampler: MIDI note 60 plays the partials at their original pitch.
+pub struct PartialPlayer {
+    voices: Vec<PlaybackVoice>,
+    duration: f64,
+    limiter: Limiter,
+}
+
+impl Synth<PlaybackVoice> for PartialPlayer {
+    fn get_voices(&self) -> &[PlaybackVoice] {
+        self.voices.as_slice()
+    }
+
+    fn get_voices_mut(&mut self) -> &mut [PlaybackVoice] {
+        self.voices.as_mut_slice()
+    }
+
+    fn voices_changed(&mut self) {
+        let headroom = headroom(&self.voices);
+        for voice in self.voices.iter_mut() {
+            voice.voice.set_headroom(headroom);
+        }
+    }
+}
+
+impl PartialPlayer {
+    pub fn new(frames: Vec<Frame>, sample_rate: f32, seed: u64) -> Self {
+        let duration = frames.last().map_or(0.0, |frame| frame.time);
+        let frames = Arc::new(frames);
+        let voices = (0..8)
+            .map(|index| PlaybackVoice {
+                voice: ReconstructorVoice::new(sample_rate, seed.wrapping_add(index)),
+                frames: frames.clone(),
+                settings: PlaybackSettings {
+                    speed: 1.0,
+                    loop_region: None,
+                },
+                position: 0.0,
+            })
+            .collect();
+        Self {
+            voices,
+            duration,
+            limiter: Limiter::new(sample_rate),
+        }
+    }
+
+    /// Loads partial tracks from an SDIF file.
+    pub fn load<P: AsRef<Path>>(path: P, sample_rate: f32, seed: u64) -> io::Result<Self> {
+        let frames = sdif::read_frames(BufReader::new(File::open(path)?))?;
+        Ok(Self::new(frames, sample_rate, seed))
+    }
+
+    /// Length of the partial tracks in seconds.
+    pub fn duration(&self) -> f64 {
+        self.duration
+    }
+
+    /// Playback speed relative to the original timing; the pitch stays the same.
+    pub fn set_speed(&mut self, speed: f32) {
+        for voice in self.voices.iter_mut() {
+            voice.settings.speed = speed.max(0.0) as f64;
+        }
+    }
+
+    /// Loops between two times in seconds once the playhead reaches the end.
+    pub fn set_loop_region(&mut self, region: Option<(f64, f64)>) {
+        let region = region
+            .map(|(start, end)| (start.clamp(0.0, self.duration), end.clamp(0.0, self.duration)))
+            .filter(|(start, end)| start < end);
+        for voice in self.voices.iter_mut() {
+            voice.settings.loop_region = region;
+        }
+    }
+
+    /// Spreads the partials across the stereo field, like
+    /// `Reconstructor::set_panning`.
+    pub fn set_panning(&mut self, mode: PanMode, width: f32) {
+        for voice in self.voices.iter_mut() {
+            voice.voice.set_panning(mode, width.clamp(0.0, 1.0), 0.0);
+        }
+    }
+
+    /// Output gain in dB, applied before the limiter.
+    pub fn set_output_gain(&mut self, db: f32) {
+        self.limiter.set_gain(db);
+    }
+
+    /// How the output is kept within -1 to 1, like
+    /// `Reconstructor::set_limiter_mode`.
+    pub fn set_limiter_mode(&mut self, mode: LimiterMode) {
+        self.limiter.set_mode(mode);
+    }
+
+    /// The output lags the notes by the limiter's lookahead.
+    pub fn latency(&self) -> usize {
+        LOOKAHEAD
+    }
+
+    pub fn run(&mut self, output: [&mut [f32]; 2], events: &[Event]) {
+        let [left, right] = output;
+        assert!(left.len() == right.len());
+        assert_no_alloc(|| {
+            self.render_block(left, right, events);
+            self.limiter.process(left, right);
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::voice::EventData;
+
+    fn frames() -> Vec<Frame> {
+        (0..=10)
+            .map(|index| {
+                let mut frame = Frame {
+                    time: index as f64 * 0.1,
+                    peaks: [None; MAX_PEAKS],
+                    track_ids: [0; MAX_PEAKS],
+                };
+                frame.peaks[0] = Some(Peak {
+                    frequency: 400.0 + index as f32 * 10.0,
+                    amplitude: 0.5,
+                    phase: 0.0,
+                });
+                frame
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_peaks_at() {
+        let frames = frames();
+        let peak = peaks_at(&frames, 0.25)[0].unwrap();
+        assert!((peak.frequency - 425.0).abs() < 0.001);
+        assert!(peaks_at(&frames, -0.1)[0].is_none());
+        assert!(peaks_at(&frames, 1.1)[0].is_none());
+    }
+
+    #[test]
+    fn test_playback() {
+        let mut player = PartialPlayer::new(frames(), 1000.0, 0);
+        player.set_speed(2.0);
+        player.set_loop_region(Some((0.2, 0.6)));
+
+        player.set_panning(PanMode::Alternating, 1.0);
+        let (mut left, mut right) = ([0_f32; 100], [0_f32; 100]);
+        player.run([&mut left, &mut right], &[]);
+        assert!(left.iter().all(|x| *x == 0.0));
+
+        let note_on = Event {
+            offset: 0.0,
+            data: EventData::NoteOn {
+                note_number: 60,
+                velocity: 127,
+            },
+        };
+        player.run([&mut left, &mut right], &[note_on]);
+        let voice = player.voices.iter().find(|v| !v.is_free()).unwrap();
+        assert!((voice.position - 0.4).abs() < 1e-9);
+        player.run([&mut left, &mut right], &[]);
+        player.run([&mut left, &mut right], &[]);
+        let voice = player.voices.iter().find(|v| !v.is_free()).unwrap();
+        assert!((voice.position - 0.4).abs() < 1e-9);
+        // The only partial is in slot 0, which is panned hard left
+        assert!(left.iter().any(|x| x.abs() > 0.01));
+        assert!(right.iter().all(|x| x.abs() < 1e-6));
+    }
+
+    #[test]
+    fn test_headroom() {
+        let peak = |notes: u8| {
+            let mut player = PartialPlayer::new(frames(), 48000.0, 0);
+            let events: Vec<Event> = (60..60 + notes)
+                .map(|note_number| Event {
+                    offset: 0.0,
+                    data: EventData::NoteOn {
+                        note_number,
+                        velocity: 127,
+                    },
+                })
+                .collect();
+            let (mut left, mut right) = ([0_f32; 512], [0_f32; 512]);
+            let mut peak = 0_f32;
+            for block in 0..8 {
+                let events = if block == 0 { &events[..] } else { &[] };
+                player.run([&mut left, &mut right], events);
+                peak = left.iter().fold(peak, |peak, x| peak.max(x.abs()));
+            }
+            peak
+        };
+        let single = peak(1);
+        assert!(single > 0.01);
+        assert!(peak(8) < single * 4.0);
+    }
+}
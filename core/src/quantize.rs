@@ -0,0 +1,165 @@
+use crate::peak::{Peak, MAX_PEAKS};
+
+pub const MAX_SCALE_DEGREES: usize = 128;
+
+/// Built in scales, tuned in 12 tone equal temperament.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScaleKind {
+    Chromatic,
+    Major,
+    Minor,
+    /// The major pentatonic scale
+    Pentatonic,
+}
+
+impl ScaleKind {
+    fn cents(self) -> &'static [f32] {
+        match self {
+            ScaleKind::Chromatic => &[
+                0.0, 100.0, 200.0, 300.0, 400.0, 500.0, 600.0, 700.0, 800.0, 900.0, 1000.0, 1100.0,
+            ],
+            ScaleKind::Major => &[0.0, 200.0, 400.0, 500.0, 700.0, 900.0, 1100.0],
+            ScaleKind::Minor => &[0.0, 200.0, 300.0, 500.0, 700.0, 800.0, 1000.0],
+            ScaleKind::Pentatonic => &[0.0, 200.0, 400.0, 700.0, 900.0],
+        }
+    }
+}
+
+/// The pitches of a scale that repeats every `period` cents from a root
+/// frequency.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Scale {
+    // cents above the root within one period, ascending
+    degrees: [f32; MAX_SCALE_DEGREES],
+    len: usize,
+    period: f32,
+    root: f32,
+}
+
+impl Scale {
+    pub fn new(kind: ScaleKind, root: f32) -> Self {
+        Self::from_cents(kind.cents(), 1200.0, root).unwrap()
+    }
+
+    /// A scale from its pitches in cents above the root. They are wrapped
+    /// into the period and sorted. Returns `None` without any pitches, with
+    /// more than `MAX_SCALE_DEGREES` or with a period or root that isn't
+    /// positive.
+    pub fn from_cents(cents: &[f32], period: f32, root: f32) -> Option<Self> {
+        let valid = !cents.is_empty()
+            && cents.len() <= MAX_SCALE_DEGREES
+            && cents.iter().all(|cents| cents.is_finite())
+            && period > 0.0
+            && root > 0.0;
+        if !valid {
+            return None;
+        }
+        let mut degrees = [0.0; MAX_SCALE_DEGREES];
+        for (degree, cents) in degrees.iter_mut().zip(cents.iter()) {
+            *degree = cents.rem_euclid(period);
+        }
+        degrees[0..cents.len()].sort_unstable_by(f32::total_cmp);
+        Some(Self {
+            degrees,
+            len: cents.len(),
+            period,
+            root,
+        })
+    }
+
+    /// The pitch of the scale closest to `frequency` in cents.
+    pub fn nearest(&self, frequency: f32) -> f32 {
+        let cents = 1200.0 * (frequency / self.root).log2();
+        let period_start = (cents / self.period).floor() * self.period;
+        let within = cents - period_start;
+        // The first degree of the next period or the last one of the
+        // previous period may be closer than any in this one
+        let next_period = self.degrees[0] + self.period;
+        let previous_period = self.degrees[self.len - 1] - self.period;
+        let nearest = self.degrees[0..self.len]
+            .iter()
+            .chain([next_period, previous_period].iter())
+            .fold(f32::INFINITY, |nearest, degree| {
+                if (degree - within).abs() < (nearest - within).abs() {
+                    *degree
+                } else {
+                    nearest
+                }
+            });
+        self.root * 2_f32.powf((period_start + nearest) / 1200.0)
+    }
+}
+
+/// What partial frequencies are snapped to.
+// Not boxed so it can be set from the audio thread
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Quantize {
+    Off,
+    Scale(Scale),
+    /// Multiples of a fundamental frequency in Hz
+    Harmonics(f32),
+}
+
+/// Moves each peak's frequency `amount` of the way, between 0 and 1 in log
+/// frequency, to the nearest allowed frequency.
+pub fn quantize(
+    peaks: &[Option<Peak>; MAX_PEAKS],
+    quantize: &Quantize,
+    amount: f32,
+) -> [Option<Peak>; MAX_PEAKS] {
+    let amount = amount.clamp(0.0, 1.0);
+    let mut quantized = *peaks;
+    for peak in quantized.iter_mut().flatten().filter(|peak| peak.frequency > 0.0) {
+        let target = match quantize {
+            Quantize::Off => continue,
+            Quantize::Scale(scale) => scale.nearest(peak.frequency),
+            Quantize::Harmonics(fundamental) if *fundamental > 0.0 => {
+                (peak.frequency / fundamental).round().max(1.0) * fundamental
+            }
+            Quantize::Harmonics(_) => continue,
+        };
+        peak.frequency *= (target / peak.frequency).powf(amount);
+    }
+    quantized
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_quantize() {
+        let a = Scale::new(ScaleKind::Major, 440.0);
+        assert!((a.nearest(450.0) - 440.0).abs() < 1e-3);
+        // A# is between A and B, a little closer to B
+        assert!((a.nearest(470.0) - 493.8833).abs() < 1e-2);
+        // Just below the root the next period's root is nearest
+        assert!((a.nearest(435.0) - 440.0).abs() < 1e-3);
+        assert!((a.nearest(110.5) - 110.0).abs() < 1e-3);
+
+        let custom = Scale::from_cents(&[1250.0, 0.0], 1200.0, 100.0).unwrap();
+        assert!((custom.nearest(101.0) - 100.0).abs() < 1e-3);
+        assert!((custom.nearest(105.0) - 100.0 * 2_f32.powf(50.0 / 1200.0)).abs() < 1e-3);
+        assert!(Scale::from_cents(&[], 1200.0, 100.0).is_none());
+        // Without a degree at the root the previous period's last degree
+        // can be nearest
+        let fifth_seventh = Scale::from_cents(&[700.0, 1100.0], 1200.0, 100.0).unwrap();
+        let up = 100.0 * 2_f32.powf(100.0 / 1200.0);
+        let down = 100.0 * 2_f32.powf(-100.0 / 1200.0);
+        assert!((fifth_seventh.nearest(up) - down).abs() < 1e-3);
+
+        let mut peaks = [None; MAX_PEAKS];
+        peaks[2] = Some(Peak {
+            frequency: 310.0,
+            amplitude: 0.5,
+            phase: 0.0,
+        });
+        let harmonics = quantize(&peaks, &Quantize::Harmonics(100.0), 1.0);
+        assert!((harmonics[2].unwrap().frequency - 300.0).abs() < 1e-3);
+        assert_eq!(harmonics[2].unwrap().amplitude, 0.5);
+        let halfway = quantize(&peaks, &Quantize::Harmonics(100.0), 0.5);
+        assert!((halfway[2].unwrap().frequency - (310.0_f32 * 300.0).sqrt()).abs() < 1e-3);
+        assert_eq!(quantize(&peaks, &Quantize::Off, 1.0), peaks);
+    }
+}
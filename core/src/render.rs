@@ -0,0 +1,203 @@
+use crate::limiter::LimiterMode;
+use crate::osc::Waveform;
+use crate::pan::PanMode;
+use crate::partial_filter::PartialFilter;
+use crate::peak::MAX_PEAKS;
+use crate::quantize::Quantize;
+use crate::reconstructor::Reconstructor;
+use crate::smooth::SmoothingCurve;
+
+#[derive(Debug, Clone)]
+pub struct RenderSettings {
+    /// In octaves, like `Reconstructor::set_transpose`
+    pub transpose: f32,
+    /// Keep the spectral envelope in place when transposing
+    pub formant_preserve: bool,
+    /// In octaves, like `Reconstructor::set_formant_shift`
+    pub formant_shift: f32,
+    /// In Hz, like `Reconstructor::set_frequency_shift`
+    pub frequency_shift: f32,
+    pub stretch: f32,
+    /// In Hz, the frequency that stays in place when stretching
+    pub stretch_pivot: f32,
+    pub partial_filter: PartialFilter,
+    /// In seconds, 64 samples if not set
+    pub frequency_glide: Option<f32>,
+    pub amplitude_glide: Option<f32>,
+    pub amplitude_curve: SmoothingCurve,
+    pub waveform: Waveform,
+    /// The single cycle played by `Waveform::Wavetable`
+    pub wavetable: Option<Vec<f32>>,
+    pub quantize: Quantize,
+    /// How far partials move to their quantized frequencies, 0 to 1
+    pub quantize_amount: f32,
+    pub detune: f32,
+    /// In Hz, like `Reconstructor::set_drift_rate`
+    pub drift_rate: f32,
+    /// The level of the residual noise between 0 and 1
+    pub noise_level: f32,
+    /// How much of the dry input passes through at transients, 0 to 1
+    pub transient_mix: f32,
+    /// Freeze the spectrum from this time in seconds onwards
+    pub freeze_at: Option<f64>,
+    pub partial_count: usize,
+    pub pan_mode: PanMode,
+    /// Stereo spread between 0 (mono) and 1
+    pub width: f32,
+    /// 0 is only the input, 1 is only the resynthesis
+    pub mix: f32,
+    /// In dB, before the limiter
+    pub output_gain: f32,
+    pub limiter_mode: LimiterMode,
+    pub block_size: usize,
+    pub seed: u64,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        Self {
+            transpose: 0.0,
+            formant_preserve: false,
+            formant_shift: 0.0,
+            frequency_shift: 0.0,
+            stretch: 1.0,
+            stretch_pivot: 261.63,
+            partial_filter: PartialFilter::default(),
+            frequency_glide: None,
+            amplitude_glide: None,
+            amplitude_curve: SmoothingCurve::Linear,
+            waveform: Waveform::Sine,
+            wavetable: None,
+            quantize: Quantize::Off,
+            quantize_amount: 1.0,
+            detune: 0.0,
+            drift_rate: 0.0,
+            noise_level: 0.0,
+            transient_mix: 0.0,
+            freeze_at: None,
+            partial_count: MAX_PEAKS,
+            pan_mode: PanMode::Index,
+            width: 0.0,
+            mix: 1.0,
+            output_gain: 0.0,
+            limiter_mode: LimiterMode::HardClip,
+            block_size: 256,
+            seed: 0,
+        }
+    }
+}
+
+/// Renders a signal through the `Reconstructor` in fixed size blocks, so the
+/// output only depends on the input and the settings. Returns the left and
+/// right channels.
+pub fn render(input: &[f32], sample_rate: f32, settings: &RenderSettings) -> [Vec<f32>; 2] {
+    assert!(settings.block_size > 0);
+    let mut reconstructor = Reconstructor::with_seed(sample_rate, settings.seed);
+    reconstructor.set_transpose(settings.transpose);
+    reconstructor.set_formant_preserve(settings.formant_preserve);
+    reconstructor.set_formant_shift(settings.formant_shift);
+    reconstructor.set_frequency_shift(settings.frequency_shift);
+    reconstructor.set_stretch(settings.stretch, settings.stretch_pivot);
+    reconstructor.set_partial_filter(settings.partial_filter);
+    if let Some(seconds) = settings.frequency_glide {
+        reconstructor.set_frequency_glide(seconds);
+    }
+    if let Some(seconds) = settings.amplitude_glide {
+        reconstructor.set_amplitude_glide(seconds);
+    }
+    reconstructor.set_amplitude_curve(settings.amplitude_curve);
+    if let Some(cycle) = &settings.wavetable {
+        reconstructor.set_wavetable(cycle);
+    }
+    reconstructor.set_waveform(settings.waveform);
+    reconstructor.set_quantize(settings.quantize);
+    reconstructor.set_quantize_amount(settings.quantize_amount);
+    reconstructor.set_detune(settings.detune);
+    reconstructor.set_drift_rate(settings.drift_rate);
+    reconstructor.set_noise_level(settings.noise_level);
+    reconstructor.set_transient_mix(settings.transient_mix);
+    reconstructor.set_mix(settings.mix);
+    reconstructor.set_output_gain(settings.output_gain);
+    reconstructor.set_limiter_mode(settings.limiter_mode);
+    reconstructor.set_partial_count(settings.partial_count);
+    reconstructor.set_panning(settings.pan_mode, settings.width);
+
+    // Run on past the end of the input by the latency and drop as much from
+    // the start, so the output lines up with the input
+    let latency = reconstructor.latency();
+    let mut padded = input.to_vec();
+    padded.resize(input.len() + latency, 0.0);
+    let mut left = vec![0_f32; padded.len()];
+    let mut right = vec![0_f32; padded.len()];
+    for (index, ((input_block, left_block), right_block)) in padded
+        .chunks(settings.block_size)
+        .zip(left.chunks_mut(settings.block_size))
+        .zip(right.chunks_mut(settings.block_size))
+        .enumerate()
+    {
+        let time = (index * settings.block_size) as f64 / sample_rate as f64;
+        reconstructor.set_freeze(
+            settings
+                .freeze_at
+                .is_some_and(|freeze_at| time >= freeze_at),
+        );
+        reconstructor.run([input_block, input_block], [left_block, right_block], &[]);
+    }
+    left.drain(0..latency);
+    right.drain(0..latency);
+    [left, right]
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::utils::build_sample;
+
+    #[test]
+    fn test_render() {
+        let input = build_sample(&[(440.0, 0.5, 0.0), (1000.0, 0.25, 0.0)], 24000, 48000.0);
+        let settings = RenderSettings {
+            detune: 0.5,
+            drift_rate: 2.0,
+            freeze_at: Some(0.25),
+            seed: 3,
+            ..RenderSettings::default()
+        };
+        let first = render(&input, 48000.0, &settings);
+        let second = render(&input, 48000.0, &settings);
+        assert_eq!(first, second);
+        assert!(first[0].iter().any(|x| x.abs() > 0.1));
+        // Without any width both channels are the same
+        assert_eq!(first[0], first[1]);
+
+        let other_seed = render(
+            &input,
+            48000.0,
+            &RenderSettings {
+                seed: 4,
+                ..settings.clone()
+            },
+        );
+        assert_ne!(first, other_seed);
+
+        let noisy = render(
+            &input,
+            48000.0,
+            &RenderSettings {
+                noise_level: 1.0,
+                ..settings.clone()
+            },
+        );
+        assert_ne!(first, noisy);
+
+        let dry = render(
+            &input,
+            48000.0,
+            &RenderSettings {
+                mix: 0.0,
+                ..settings.clone()
+            },
+        );
+        assert_eq!(dry, [input.clone(), input.clone()]);
+    }
+}
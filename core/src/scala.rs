@@ -0,0 +1,294 @@
+use crate::quantize::{Scale, MAX_SCALE_DEGREES};
+use std::io;
+use std::path::Path;
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// The lines of a Scala file without comments. Values are the first word of
+/// a line, anything after them is ignored.
+fn values(text: &str) -> impl Iterator<Item = &str> {
+    text.lines()
+        .filter(|line| !line.starts_with('!'))
+        .map(|line| line.split_whitespace().next().unwrap_or(""))
+}
+
+fn parse_value<T: std::str::FromStr>(value: Option<&str>, name: &str) -> io::Result<T> {
+    value
+        .and_then(|value| value.parse().ok())
+        .ok_or_else(|| invalid_data(&format!("invalid {}", name)))
+}
+
+/// A pitch of a .scl file in cents: with a period it is in cents, otherwise
+/// it is a ratio like 3/2 or 2.
+fn parse_pitch(value: &str) -> io::Result<f32> {
+    let invalid = || invalid_data(&format!("invalid pitch {}", value));
+    if value.contains('.') {
+        return value.parse().map_err(|_| invalid());
+    }
+    let (numerator, denominator) = value.split_once('/').unwrap_or((value, "1"));
+    let numerator: f64 = numerator.parse().map_err(|_| invalid())?;
+    let denominator: f64 = denominator.parse().map_err(|_| invalid())?;
+    if numerator <= 0.0 || denominator <= 0.0 {
+        return Err(invalid());
+    }
+    Ok((1200.0 * (numerator / denominator).log2()) as f32)
+}
+
+/// Reads the pitches of a .scl file in cents above the root. The last one is
+/// the period the scale repeats at.
+pub fn parse_scl(text: &str) -> io::Result<Vec<f32>> {
+    // The description may be empty, so only comments are skipped before it
+    let mut lines = text.lines().filter(|line| !line.starts_with('!')).skip(1);
+    let count: usize = parse_value(
+        lines.next().and_then(|line| line.split_whitespace().next()),
+        "number of notes",
+    )?;
+    let pitches = lines
+        .filter_map(|line| line.split_whitespace().next())
+        .take(count)
+        .map(parse_pitch)
+        .collect::<io::Result<Vec<f32>>>()?;
+    if pitches.len() < count || count == 0 {
+        return Err(invalid_data("missing notes"));
+    }
+    if pitches[count - 1] <= 0.0 {
+        return Err(invalid_data("the period must be above the root"));
+    }
+    Ok(pitches)
+}
+
+/// How MIDI notes map onto scale degrees, as in a .kbm file.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct KeyboardMapping {
+    pub first_note: u8,
+    pub last_note: u8,
+    /// The note that plays the root of the scale
+    pub middle_note: u8,
+    pub reference_note: u8,
+    pub reference_frequency: f32,
+    /// The degree the mapping repeats at, 0 for the scale's period
+    pub octave_degree: usize,
+    /// The degree of each key from the middle note on, `None` for keys that
+    /// don't play. An empty mapping maps every key to the next degree.
+    pub keys: Vec<Option<usize>>,
+}
+
+impl Default for KeyboardMapping {
+    /// Every key is mapped, with the root on middle C and A at 440 Hz.
+    fn default() -> Self {
+        Self {
+            first_note: 0,
+            last_note: 127,
+            middle_note: 60,
+            reference_note: 69,
+            reference_frequency: 440.0,
+            octave_degree: 0,
+            keys: vec![],
+        }
+    }
+}
+
+/// Reads a .kbm keyboard mapping.
+pub fn parse_kbm(text: &str) -> io::Result<KeyboardMapping> {
+    let mut values = values(text).filter(|value| !value.is_empty());
+    let map_size: usize = parse_value(values.next(), "map size")?;
+    let mut mapping = KeyboardMapping {
+        first_note: parse_value(values.next(), "first note")?,
+        last_note: parse_value(values.next(), "last note")?,
+        middle_note: parse_value(values.next(), "middle note")?,
+        reference_note: parse_value(values.next(), "reference note")?,
+        reference_frequency: parse_value(values.next(), "reference frequency")?,
+        octave_degree: parse_value(values.next(), "octave degree")?,
+        keys: vec![],
+    };
+    if mapping.reference_frequency <= 0.0 {
+        return Err(invalid_data("invalid reference frequency"));
+    }
+    // Keys missing at the end of the file don't play
+    mapping.keys = (0..map_size)
+        .map(|_| match values.next() {
+            None | Some("x") => Ok(None),
+            value => parse_value(value, "key").map(Some),
+        })
+        .collect::<io::Result<_>>()?;
+    Ok(mapping)
+}
+
+/// A scale and the keyboard mapping that tunes MIDI notes to it.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Tuning {
+    /// In cents above the root, the last one is the period
+    pub pitches: Vec<f32>,
+    pub mapping: KeyboardMapping,
+}
+
+impl Default for Tuning {
+    /// 12 tone equal temperament.
+    fn default() -> Self {
+        Self {
+            pitches: (1..=12).map(|degree| degree as f32 * 100.0).collect(),
+            mapping: KeyboardMapping::default(),
+        }
+    }
+}
+
+impl Tuning {
+    /// Checks that the reference note plays, so every other note can be
+    /// tuned from it.
+    pub fn new(pitches: Vec<f32>, mapping: KeyboardMapping) -> io::Result<Self> {
+        let tuning = Self { pitches, mapping };
+        if tuning.pitches.is_empty() {
+            return Err(invalid_data("the scale has no notes"));
+        }
+        if tuning.cents(tuning.mapping.reference_note).is_none() {
+            return Err(invalid_data("the reference note is not mapped"));
+        }
+        Ok(tuning)
+    }
+
+    /// Loads a .scl file and optionally a .kbm file, the default mapping
+    /// otherwise.
+    pub fn load<P: AsRef<Path>>(scl: P, kbm: Option<P>) -> io::Result<Self> {
+        let kbm = kbm.map(std::fs::read_to_string).transpose()?;
+        Self::parse(&std::fs::read_to_string(scl)?, kbm.as_deref())
+    }
+
+    /// Like `load`, from the contents of the files.
+    pub fn parse(scl: &str, kbm: Option<&str>) -> io::Result<Self> {
+        let mapping = match kbm {
+            Some(kbm) => parse_kbm(kbm)?,
+            None => KeyboardMapping::default(),
+        };
+        Self::new(parse_scl(scl)?, mapping)
+    }
+
+    fn degree_cents(&self, degree: i32) -> f32 {
+        let count = self.pitches.len() as i32;
+        let period = self.pitches[self.pitches.len() - 1];
+        let within = degree.rem_euclid(count);
+        let below = if within == 0 {
+            0.0
+        } else {
+            self.pitches[within as usize - 1]
+        };
+        degree.div_euclid(count) as f32 * period + below
+    }
+
+    /// Cents above the root of the scale, `None` for notes that don't play.
+    fn cents(&self, note: u8) -> Option<f32> {
+        let mapping = &self.mapping;
+        if note < mapping.first_note || note > mapping.last_note {
+            return None;
+        }
+        let offset = note as i32 - mapping.middle_note as i32;
+        if mapping.keys.is_empty() {
+            return Some(self.degree_cents(offset));
+        }
+        let size = mapping.keys.len() as i32;
+        let key = mapping.keys[offset.rem_euclid(size) as usize]?;
+        let octave = match mapping.octave_degree {
+            0 => self.pitches.len(),
+            degree => degree,
+        };
+        let repeats = offset.div_euclid(size) as f32;
+        Some(self.degree_cents(key as i32) + repeats * self.degree_cents(octave as i32))
+    }
+
+    /// The frequency of a MIDI note, `None` for notes that don't play.
+    pub fn frequency(&self, note: u8) -> Option<f32> {
+        let reference = self.cents(self.mapping.reference_note)?;
+        let cents = self.cents(note)?;
+        Some(self.mapping.reference_frequency * 2_f32.powf((cents - reference) / 1200.0))
+    }
+
+    /// The scale for quantizing partials, rooted on the middle note. `None`
+    /// if it has more pitches than a `Scale` holds. Doesn't allocate.
+    pub fn scale(&self) -> Option<Scale> {
+        let root = self.frequency(self.mapping.middle_note)?;
+        let count = self.pitches.len();
+        if count > MAX_SCALE_DEGREES {
+            return None;
+        }
+        // The root takes the place of the period
+        let mut cents = [0.0; MAX_SCALE_DEGREES];
+        cents[1..count].copy_from_slice(&self.pitches[0..count - 1]);
+        Scale::from_cents(&cents[0..count], self.pitches[count - 1], root)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const SCL: &str = "! just.scl
+!
+Just major
+ 7
+!
+ 9/8
+ 5/4
+ 4/3
+ 3/2 the fifth
+ 5/3
+ 15/8
+ 2
+";
+
+    const KBM: &str = "! white keys only
+12
+0
+127
+60
+69
+440.0
+7
+! the keys
+0
+x
+1
+x
+2
+3
+x
+4
+x
+5
+x
+";
+
+    #[test]
+    fn test_tuning() {
+        let pitches = parse_scl(SCL).unwrap();
+        assert_eq!(pitches.len(), 7);
+        assert!((pitches[3] - 701.955).abs() < 1e-3);
+        assert!((parse_pitch("100.5").unwrap() - 100.5).abs() < 1e-6);
+        assert!(parse_scl("description\n3\n100.0\n").is_err());
+
+        let equal = Tuning::default();
+        assert!((equal.frequency(69).unwrap() - 440.0).abs() < 1e-3);
+        assert!((equal.frequency(57).unwrap() - 220.0).abs() < 1e-3);
+        assert!((equal.frequency(60).unwrap() - 261.6256).abs() < 1e-3);
+
+        let mapping = parse_kbm(KBM).unwrap();
+        // Keys past the end of the file don't play
+        assert_eq!(mapping.keys[9..], [Some(5), None, None]);
+        let just = Tuning::new(pitches, mapping).unwrap();
+        assert_eq!(Tuning::parse(SCL, Some(KBM)).unwrap(), just);
+        // A mapping that leaves out the reference note can't be tuned
+        assert!(Tuning::parse(SCL, Some("1\n0\n127\n60\n69\n440.0\n0\nx\n")).is_err());
+        assert!(just.frequency(61).is_none());
+        assert!((just.frequency(69).unwrap() - 440.0).abs() < 1e-3);
+        let c = just.frequency(60).unwrap();
+        assert!((c - 264.0).abs() < 1e-2);
+        assert!((just.frequency(67).unwrap() / c - 1.5).abs() < 1e-4);
+        assert!((just.frequency(72).unwrap() / c - 2.0).abs() < 1e-4);
+        assert!((just.frequency(48).unwrap() / c - 0.5).abs() < 1e-4);
+
+        let scale = Tuning::default().scale().unwrap();
+        assert!((scale.nearest(445.0) - 440.0).abs() < 1e-2);
+    }
+}
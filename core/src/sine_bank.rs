@@ -0,0 +1,152 @@
+use std::f32::consts::PI;
+use std::simd::prelude::*;
+use std::sync::OnceLock;
+
+pub const LANES: usize = 8;
+/// The most oscillators a bank runs, a whole number of vectors.
+pub const BANK_SIZE: usize = 24;
+const GROUPS: usize = BANK_SIZE / LANES;
+// Linear interpolation in a table this long is within 3e-7 of a sine
+const TABLE_SIZE: usize = 4096;
+
+type Lanes = Simd<f32, LANES>;
+
+/// One cycle of a sine, with the first value repeated at the end.
+fn sine_table() -> &'static [f32; TABLE_SIZE + 1] {
+    static TABLE: OnceLock<[f32; TABLE_SIZE + 1]> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        std::array::from_fn(|index| {
+            (2.0 * std::f64::consts::PI * index as f64 / TABLE_SIZE as f64).sin() as f32
+        })
+    })
+}
+
+/// `phase` in radians, between 0 and 2π.
+fn sine(phase: Lanes, table: &[f32; TABLE_SIZE + 1]) -> Lanes {
+    let position = phase * Lanes::splat(TABLE_SIZE as f32 / (2.0 * PI));
+    let index = position.cast::<usize>().simd_min(Simd::splat(TABLE_SIZE - 1));
+    let fraction = position - index.cast::<f32>();
+    let before = Simd::gather_or_default(table, index);
+    let after = Simd::gather_or_default(table, index + Simd::splat(1));
+    before + (after - before) * fraction
+}
+
+/// One sample of every oscillator in a bank.
+#[derive(Debug, Clone, Copy)]
+pub struct BankFrame {
+    /// Radians per sample, up to π
+    pub frequencies: [f32; BANK_SIZE],
+    pub amplitudes: [f32; BANK_SIZE],
+    pub left_gains: [f32; BANK_SIZE],
+    pub right_gains: [f32; BANK_SIZE],
+}
+
+impl Default for BankFrame {
+    /// Silent.
+    fn default() -> Self {
+        Self {
+            frequencies: [0.0; BANK_SIZE],
+            amplitudes: [0.0; BANK_SIZE],
+            left_gains: [0.0; BANK_SIZE],
+            right_gains: [0.0; BANK_SIZE],
+        }
+    }
+}
+
+/// Sine oscillators that run side by side in SIMD lanes, reading a table
+/// instead of calling `sin`. Their phases advance like `Osc`'s.
+#[derive(Debug)]
+pub struct SineBank {
+    phases: [Lanes; GROUPS],
+}
+
+impl Default for SineBank {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SineBank {
+    pub fn new() -> Self {
+        // Build the table now rather than on the audio thread
+        sine_table();
+        Self {
+            phases: [Lanes::splat(0.0); GROUPS],
+        }
+    }
+
+    pub fn phase(&self, index: usize) -> f32 {
+        self.phases[index / LANES][index % LANES]
+    }
+
+    /// Any phase in radians, it is wrapped between 0 and 2π.
+    pub fn set_phase(&mut self, index: usize, phase: f32) {
+        self.phases[index / LANES][index % LANES] = phase.rem_euclid(2.0 * PI);
+    }
+
+    /// Adds one sample per frame to `left` and `right`, each the sum of the
+    /// oscillators times their gains.
+    pub fn render(&mut self, frames: &[BankFrame], left: &mut [f32], right: &mut [f32]) {
+        let table = sine_table();
+        let two_pi = Lanes::splat(2.0 * PI);
+        for (frame, (left, right)) in frames.iter().zip(left.iter_mut().zip(right.iter_mut())) {
+            let mut left_sum = Lanes::splat(0.0);
+            let mut right_sum = Lanes::splat(0.0);
+            for (group, phase) in self.phases.iter_mut().enumerate() {
+                let lanes = group * LANES..(group + 1) * LANES;
+                let sample = sine(*phase, table) * Lanes::from_slice(&frame.amplitudes[lanes.clone()]);
+                left_sum += sample * Lanes::from_slice(&frame.left_gains[lanes.clone()]);
+                right_sum += sample * Lanes::from_slice(&frame.right_gains[lanes.clone()]);
+                // Exactly what the remainder gives, as the step is at most π
+                let advanced = *phase + Lanes::from_slice(&frame.frequencies[lanes]);
+                *phase = advanced.simd_ge(two_pi).select(advanced - two_pi, advanced);
+            }
+            *left += left_sum.reduce_sum();
+            *right += right_sum.reduce_sum();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::drift;
+    use crate::osc::Osc;
+
+    #[test]
+    fn test_sine_bank() {
+        let mut bank = SineBank::new();
+        let mut oscs: Vec<Osc> = (0..BANK_SIZE)
+            .map(|index| {
+                let phase = drift::random(index as u64) * PI;
+                bank.set_phase(index, phase);
+                Osc::new(0.0, 0.0, phase)
+            })
+            .collect();
+        let mut frames = vec![BankFrame::default(); 4800];
+        let mut expected = vec![0_f32; 4800];
+        for (sample, frame) in frames.iter_mut().enumerate() {
+            for (index, osc) in oscs.iter_mut().enumerate() {
+                // Gliding frequencies up to near Nyquist
+                let hz = 50.0 + 900.0 * index as f32 + sample as f32 * 0.1;
+                osc.set_frequency_hz(hz, 48000.0);
+                osc.set_amplitude(1.0 / BANK_SIZE as f32);
+                frame.frequencies[index] = osc.frequency();
+                frame.amplitudes[index] = osc.gain();
+                frame.left_gains[index] = 1.0;
+                frame.right_gains[index] = 0.5;
+                expected[sample] += osc.next();
+            }
+        }
+        let (mut left, mut right) = (vec![0_f32; 4800], vec![0_f32; 4800]);
+        bank.render(&frames, &mut left, &mut right);
+        for ((left, right), expected) in left.iter().zip(right.iter()).zip(expected.iter()) {
+            assert!((left - expected).abs() < 1e-5, "{} {}", left, expected);
+            assert!((right - expected * 0.5).abs() < 1e-5);
+        }
+        for (index, osc) in oscs.iter().enumerate() {
+            let difference = (bank.phase(index) - osc.phase()).rem_euclid(2.0 * PI);
+            assert!(!(1e-3..=2.0 * PI - 1e-3).contains(&difference));
+        }
+    }
+}
@@ -1,9 +1,27 @@
+// Below this a decibel ramp starts or ends, about -100 dB
+const DECIBEL_FLOOR: f32 = 1e-5;
+// An exponential ramp covers all but e^-5, under 1%, before it snaps to the
+// target
+const EXPONENTIAL_TIME_CONSTANTS: f32 = 5.0;
+
+/// The shape of the ramp to a new target.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SmoothingCurve {
+    Linear,
+    /// Fast at first, then slowing down as it nears the target
+    Exponential,
+    /// Linear in decibels, for amplitudes. Ramps to or from 0 start or end
+    /// at about -100 dB.
+    Decibels,
+}
+
 #[derive(Debug)]
 pub struct SmoothedValue {
     value: f32,
     target: f32,
     remaining_steps_to_target: usize,
     smooth_length: usize,
+    curve: SmoothingCurve,
 }
 
 impl SmoothedValue {
@@ -13,6 +31,7 @@ impl SmoothedValue {
             target: initial,
             remaining_steps_to_target: 0,
             smooth_length,
+            curve: SmoothingCurve::Linear,
         }
     }
 
@@ -23,13 +42,37 @@ impl SmoothedValue {
         }
     }
 
+    /// Takes effect from the next change of target.
+    pub fn set_smooth_length(&mut self, smooth_length: usize) {
+        self.smooth_length = smooth_length;
+    }
+
+    pub fn set_curve(&mut self, curve: SmoothingCurve) {
+        self.curve = curve;
+    }
+
     #[allow(clippy::should_implement_trait)]
     pub fn next(&mut self) -> f32 {
         if self.remaining_steps_to_target == 0 {
             return self.value;
         }
-        let step = (self.target - self.value) / self.remaining_steps_to_target as f32;
-        self.value += step;
+        let remaining = self.remaining_steps_to_target as f32;
+        self.value = match self.curve {
+            _ if self.remaining_steps_to_target == 1 => self.target,
+            SmoothingCurve::Linear => self.value + (self.target - self.value) / remaining,
+            SmoothingCurve::Exponential => {
+                // The same fraction of the way each step, so the ramp ends
+                // on time whenever the target moves
+                let coefficient = 1.0
+                    - (-EXPONENTIAL_TIME_CONSTANTS / self.smooth_length.max(1) as f32).exp();
+                self.value + (self.target - self.value) * coefficient
+            }
+            SmoothingCurve::Decibels => {
+                let from = self.value.max(DECIBEL_FLOOR);
+                let to = self.target.max(DECIBEL_FLOOR);
+                from * (to / from).powf(1.0 / remaining)
+            }
+        };
         self.remaining_steps_to_target -= 1;
         self.value
     }
@@ -52,5 +95,23 @@ mod test {
         assert!((smoothed_value.next() - 0.5).abs() < f32::EPSILON);
         assert!((smoothed_value.next() - 0.75).abs() < f32::EPSILON);
         assert!((smoothed_value.next() - 1.0).abs() < f32::EPSILON);
+
+        // Halfway through a ramp from 1 to 0.01 is -20 dB
+        let mut decibels = SmoothedValue::new(1.0, 4);
+        decibels.set_curve(SmoothingCurve::Decibels);
+        decibels.set_target(0.01);
+        decibels.next();
+        assert!((decibels.next() - 0.1).abs() < 1e-6);
+        decibels.next();
+        assert_eq!(decibels.next(), 0.01);
+
+        let mut exponential = SmoothedValue::new(0.0, 4);
+        exponential.set_curve(SmoothingCurve::Exponential);
+        exponential.set_target(1.0);
+        let first = exponential.next();
+        let second = exponential.next();
+        assert!(first > 0.25 && second - first < first);
+        exponential.next();
+        assert_eq!(exponential.next(), 1.0);
     }
 }
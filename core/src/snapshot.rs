@@ -0,0 +1,243 @@
+use crate::peak::{Peak, MAX_PEAKS};
+use crate::tracker::match_closest_peaks;
+
+pub const SNAPSHOT_SLOTS: usize = 8;
+const SLOT_FLOATS: usize = 1 + MAX_PEAKS * 4;
+const SNAPSHOT_FLOATS: usize = SNAPSHOT_SLOTS * SLOT_FLOATS;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Snapshot {
+    pub peaks: [Option<Peak>; MAX_PEAKS],
+}
+
+/// Where the resynthesized spectrum comes from. An empty slot, recalled or
+/// morphed, stands in for the live input.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SnapshotSource {
+    Live,
+    Recall(usize),
+    Morph { from: usize, to: usize, amount: f32 },
+}
+
+/// Named slots of captured spectra. Capturing copies the peaks into a slot
+/// and never allocates, so it can happen on the audio thread.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SnapshotBank {
+    names: [String; SNAPSHOT_SLOTS],
+    snapshots: [Option<Snapshot>; SNAPSHOT_SLOTS],
+}
+
+impl Default for SnapshotBank {
+    fn default() -> Self {
+        Self {
+            names: std::array::from_fn(|slot| format!("Snapshot {}", slot + 1)),
+            snapshots: [None; SNAPSHOT_SLOTS],
+        }
+    }
+}
+
+impl SnapshotBank {
+    pub fn capture(&mut self, slot: usize, peaks: &[Option<Peak>; MAX_PEAKS]) {
+        if let Some(snapshot) = self.snapshots.get_mut(slot) {
+            *snapshot = Some(Snapshot { peaks: *peaks });
+        }
+    }
+
+    pub fn clear(&mut self, slot: usize) {
+        if let Some(snapshot) = self.snapshots.get_mut(slot) {
+            *snapshot = None;
+        }
+    }
+
+    pub fn get(&self, slot: usize) -> Option<&Snapshot> {
+        self.snapshots.get(slot)?.as_ref()
+    }
+
+    pub fn name(&self, slot: usize) -> &str {
+        &self.names[slot]
+    }
+
+    pub fn set_name(&mut self, slot: usize, name: String) {
+        self.names[slot] = name;
+    }
+
+    /// Copies the captured spectra but not the names, without allocating.
+    pub fn copy_snapshots_from(&mut self, other: &SnapshotBank) {
+        self.snapshots = other.snapshots;
+    }
+
+    /// The captured spectra as plain numbers, for plugin state without serde.
+    /// Each slot is 1 or 0 for whether it holds a snapshot, followed by 1 or
+    /// 0, the frequency, amplitude and phase of each of its peaks.
+    pub fn to_floats(&self) -> Vec<f32> {
+        let mut floats = Vec::with_capacity(SNAPSHOT_FLOATS);
+        for snapshot in self.snapshots.iter() {
+            floats.push(if snapshot.is_some() { 1.0 } else { 0.0 });
+            let peaks = snapshot.map_or([None; MAX_PEAKS], |snapshot| snapshot.peaks);
+            for peak in peaks.iter() {
+                floats.extend_from_slice(&match peak {
+                    Some(peak) => [1.0, peak.frequency, peak.amplitude, peak.phase],
+                    None => [0.0; 4],
+                });
+            }
+        }
+        floats
+    }
+
+    /// Reads spectra written by `to_floats`, with the default names, which
+    /// plugin state keeps apart as text. `None`
+    /// if there are too few or too many numbers.
+    pub fn from_floats(floats: &[f32]) -> Option<Self> {
+        if floats.len() != SNAPSHOT_FLOATS {
+            return None;
+        }
+        let mut bank = Self::default();
+        for (snapshot, floats) in bank.snapshots.iter_mut().zip(floats.chunks(SLOT_FLOATS)) {
+            if floats[0] == 0.0 {
+                continue;
+            }
+            let mut peaks = [None; MAX_PEAKS];
+            for (peak, values) in peaks.iter_mut().zip(floats[1..].chunks(4)) {
+                if values[0] != 0.0 {
+                    *peak = Some(Peak {
+                        frequency: values[1],
+                        amplitude: values[2],
+                        phase: values[3],
+                    });
+                }
+            }
+            *snapshot = Some(Snapshot { peaks });
+        }
+        Some(bank)
+    }
+}
+
+/// Interpolates between two spectra. Partials that match by frequency glide
+/// from one to the other, the rest fade out of `from` and into `to`.
+pub fn morph(
+    from: &[Option<Peak>; MAX_PEAKS],
+    to: &[Option<Peak>; MAX_PEAKS],
+    amount: f32,
+) -> [Option<Peak>; MAX_PEAKS] {
+    let amount = amount.clamp(0.0, 1.0);
+    let matches = match_closest_peaks(from, to);
+    let mut matched_in_to = [false; MAX_PEAKS];
+    let mut peaks = [None; MAX_PEAKS];
+    for ((peak, from_peak), matched) in peaks.iter_mut().zip(from.iter()).zip(matches.iter()) {
+        if let Some(from_peak) = from_peak {
+            *peak = Some(match matched.and_then(|index| to[index].map(|p| (index, p))) {
+                Some((index, to_peak)) => {
+                    matched_in_to[index] = true;
+                    Peak {
+                        frequency: from_peak.frequency
+                            * (to_peak.frequency / from_peak.frequency).powf(amount),
+                        amplitude: from_peak.amplitude
+                            + (to_peak.amplitude - from_peak.amplitude) * amount,
+                        ..*from_peak
+                    }
+                }
+                None => Peak {
+                    frequency: from_peak.frequency,
+                    amplitude: from_peak.amplitude * (1.0 - amount),
+                    ..*from_peak
+                },
+            });
+        }
+    }
+    let unmatched = to
+        .iter()
+        .zip(matched_in_to.iter())
+        .filter_map(|(peak, matched)| if *matched { None } else { *peak });
+    for (peak, to_peak) in peaks.iter_mut().filter(|p| p.is_none()).zip(unmatched) {
+        *peak = Some(Peak {
+            frequency: to_peak.frequency,
+            amplitude: to_peak.amplitude * amount,
+            ..to_peak
+        });
+    }
+    peaks
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_morph() {
+        let mut from = [None; MAX_PEAKS];
+        from[0] = Some(Peak {
+            frequency: 400.0,
+            amplitude: 1.0,
+            phase: 0.0,
+        });
+        from[1] = Some(Peak {
+            frequency: 3000.0,
+            amplitude: 0.5,
+            phase: 0.0,
+        });
+        let mut to = [None; MAX_PEAKS];
+        to[5] = Some(Peak {
+            frequency: 100.0,
+            amplitude: 0.25,
+            phase: 0.0,
+        });
+        to[6] = Some(Peak {
+            frequency: 450.0,
+            amplitude: 0.5,
+            phase: 0.0,
+        });
+
+        let start = morph(&from, &to, 0.0);
+        assert_eq!(start[0], from[0]);
+        assert_eq!(start[1], from[1]);
+        assert_eq!(start[2].unwrap().amplitude, 0.0);
+
+        let middle = morph(&from, &to, 0.5);
+        let glide = middle[0].unwrap();
+        assert!((glide.frequency - (400.0_f32 * 450.0).sqrt()).abs() < 0.01);
+        assert!((glide.amplitude - 0.75).abs() < 1e-6);
+        assert!((middle[1].unwrap().amplitude - 0.25).abs() < 1e-6);
+        assert_eq!(middle[2].unwrap().frequency, 100.0);
+        assert!((middle[2].unwrap().amplitude - 0.125).abs() < 1e-6);
+
+        let end = morph(&from, &to, 1.0);
+        assert_eq!(end[0], to[6]);
+        assert_eq!(end[1].unwrap().amplitude, 0.0);
+        assert_eq!(end[2], to[5]);
+    }
+
+    #[test]
+    fn test_snapshot_bank() {
+        let mut bank = SnapshotBank::default();
+        assert_eq!(bank.name(7), "Snapshot 8");
+        let mut peaks = [None; MAX_PEAKS];
+        peaks[0] = Some(Peak {
+            frequency: 200.0,
+            amplitude: 0.5,
+            phase: 0.0,
+        });
+        bank.capture(2, &peaks);
+        bank.capture(SNAPSHOT_SLOTS, &peaks);
+        assert_eq!(bank.get(2).unwrap().peaks, peaks);
+        assert!(bank.get(3).is_none());
+
+        let mut other = SnapshotBank::default();
+        other.set_name(2, "Pad".into());
+        other.copy_snapshots_from(&bank);
+        assert_eq!(other.get(2), bank.get(2));
+        assert_eq!(other.name(2), "Pad");
+        other.clear(2);
+        assert!(other.get(2).is_none());
+
+        // An empty capture is kept apart from an empty slot
+        bank.capture(5, &[None; MAX_PEAKS]);
+        let floats = bank.to_floats();
+        let restored = SnapshotBank::from_floats(&floats).unwrap();
+        assert_eq!(restored.get(2), bank.get(2));
+        assert_eq!(restored.get(5), bank.get(5));
+        assert!(restored.get(3).is_none());
+        assert!(SnapshotBank::from_floats(&floats[1..]).is_none());
+    }
+}
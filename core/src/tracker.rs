@@ -1,4 +1,4 @@
-use crate::peak::Peak;
+use crate::peak::{Peak, MAX_PEAKS};
 use assert_no_alloc::assert_no_alloc;
 
 #[derive(Clone, Copy, Debug, PartialEq)]
@@ -33,10 +33,9 @@ fn calculate_peak_distances(
     }
 }
 
-const MAX_PEAKS: usize = 20;
 const MAX_DISTANCE: f32 = 187.5;
 
-fn match_closest_peaks<const NPEAKS: usize>(
+pub(crate) fn match_closest_peaks<const NPEAKS: usize>(
     a: &[Option<Peak>; NPEAKS],
     b: &[Option<Peak>; NPEAKS],
 ) -> [Option<usize>; NPEAKS]
@@ -74,6 +73,10 @@ where
 
 pub struct PeakTracker {
     peaks: [Option<Peak>; MAX_PEAKS],
+    // Identifies each partial across frames: a slot keeps its id while its
+    // peak is matched and gets a fresh id when a new peak takes it over.
+    track_ids: [usize; MAX_PEAKS],
+    next_track_id: usize,
 }
 
 impl Default for PeakTracker {
@@ -85,7 +88,11 @@ impl Default for PeakTracker {
 impl PeakTracker {
     pub fn new() -> Self {
         let peaks = [None; MAX_PEAKS];
-        Self { peaks }
+        Self {
+            peaks,
+            track_ids: [0; MAX_PEAKS],
+            next_track_id: 0,
+        }
     }
 
     pub fn update_peaks(&mut self, mut batch: [Option<Peak>; MAX_PEAKS]) {
@@ -98,9 +105,15 @@ impl PeakTracker {
                 }
             }
             let mut unmapped_peaks = batch.iter_mut().flatten();
-            for new_peak in new_peaks.iter_mut().filter(|p| p.is_none()) {
+            for (new_peak, track_id) in new_peaks
+                .iter_mut()
+                .zip(self.track_ids.iter_mut())
+                .filter(|(p, _)| p.is_none())
+            {
                 if let Some(peak) = unmapped_peaks.next() {
                     *new_peak = Some(*peak);
+                    *track_id = self.next_track_id;
+                    self.next_track_id += 1;
                 } else {
                     break;
                 }
@@ -112,6 +125,11 @@ impl PeakTracker {
     pub fn latest(&self) -> &[Option<Peak>; MAX_PEAKS] {
         &self.peaks
     }
+
+    /// Track ids of the peaks returned by `latest`, slot for slot.
+    pub fn track_ids(&self) -> &[usize; MAX_PEAKS] {
+        &self.track_ids
+    }
 }
 
 #[cfg(test)]
@@ -124,20 +142,24 @@ mod test {
             Some(Peak {
                 frequency: 20.0,
                 amplitude: 1.0,
+                phase: 0.0,
             }),
             Some(Peak {
                 frequency: 30.0,
                 amplitude: 1.0,
+                phase: 0.0,
             }),
         ];
         let b = [
             Some(Peak {
                 frequency: 21.0,
                 amplitude: 1.0,
+                phase: 0.0,
             }),
             Some(Peak {
                 frequency: 25.0,
                 amplitude: 1.0,
+                phase: 0.0,
             }),
         ];
         let mut result: [Option<FrequencyDistance>; 4] = [None; 4];
@@ -0,0 +1,49 @@
+// An onset has to rise this many times above the recent average flux
+const THRESHOLD: f32 = 3.0;
+// and above this absolute flux
+const MIN_FLUX: f32 = 0.5;
+// How quickly the average follows the flux, per frame
+const AVERAGE_AMOUNT: f32 = 0.2;
+
+/// Spots onsets as frames whose spectral flux jumps well above the recent
+/// average, see `PeakAnalyzer::spectral_flux`.
+#[derive(Debug, Default)]
+pub struct TransientDetector {
+    average: f32,
+}
+
+impl TransientDetector {
+    pub fn detect(&mut self, flux: f32) -> bool {
+        let onset = flux > MIN_FLUX && flux > self.average * THRESHOLD;
+        self.average += (flux - self.average) * AVERAGE_AMOUNT;
+        onset
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::analyzers::quadratic::PeakAnalyzer;
+    use crate::drift;
+    use crate::utils::build_sample;
+
+    #[test]
+    fn test_transient_detector() {
+        let mut analyzer = PeakAnalyzer::new(48000.0);
+        let mut detector = TransientDetector::default();
+        let mut detect = |frame: &[f32]| {
+            analyzer.get_raw_peaks(frame);
+            detector.detect(analyzer.spectral_flux())
+        };
+
+        let silence = [0_f32; 512];
+        assert!(!detect(&silence));
+
+        // A burst of noise is an onset, a steady tone after it is not
+        let burst: Vec<f32> = (0..512).map(|index| drift::random(index) * 0.5).collect();
+        assert!(detect(&burst));
+        let tone = build_sample(&[(440.0, 0.5, 0.0)], 512 * 8, 48000.0);
+        let onsets = tone.chunks(512).filter(|frame| detect(frame)).count();
+        assert_eq!(onsets, 0);
+    }
+}
@@ -39,6 +39,15 @@ pub fn floor_to_power_of_2(n: usize) -> Option<usize> {
     Some(1 << ilog2(n)?)
 }
 
+/// Wraps a phase in radians into the range from -π to π.
+/// ```
+/// # use core::utils::wrap_phase;
+/// assert!((wrap_phase(7.0) - (7.0 - 2.0 * std::f32::consts::PI)).abs() < 1e-6);
+/// ```
+pub fn wrap_phase(phase: f32) -> f32 {
+    (phase + PI).rem_euclid(2.0 * PI) - PI
+}
+
 pub fn build_sample(partials: &[(f32, f32, f32)], size: usize, sample_rate: f32) -> Vec<f32> {
     let mut sample = vec![0_f32; size];
     for (frequency, amplitude, phase) in partials {
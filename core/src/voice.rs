@@ -20,7 +20,9 @@ pub trait Voice {
 
     fn set_note(&mut self, note: Option<Note>);
 
-    fn render_block(&mut self, block: &mut [f32]);
+    /// Adds the voice to the left and right channels, which have the same
+    /// length.
+    fn render_block(&mut self, left: &mut [f32], right: &mut [f32]);
 
     fn note_on(&mut self, note_number: u8, _velocity: u8) {
         self.set_note(Some(Note { note_number }));
@@ -47,6 +49,9 @@ pub trait Synth<V: Voice> {
     fn get_voices(&self) -> &[V];
     fn get_voices_mut(&mut self) -> &mut [V];
 
+    /// Called whenever a note starts or stops, before the voices render on.
+    fn voices_changed(&mut self) {}
+
     fn allocate_note(&mut self, note_number: u8, velocity: u8) {
         if let Some(_voice) = self
             .get_voices_mut()
@@ -74,7 +79,7 @@ pub trait Synth<V: Voice> {
         }
     }
 
-    fn render_block(&mut self, output: &mut [f32], events: &[Event]) {
+    fn render_block(&mut self, left: &mut [f32], right: &mut [f32], events: &[Event]) {
         let mut block_start = 0;
         for event in events.iter() {
             match event.data {
@@ -88,17 +93,20 @@ pub trait Synth<V: Voice> {
                     self.deallocate_note(note_number);
                 }
             }
+            self.voices_changed();
             let block_end = event.offset as usize;
-            let block = &mut output[block_start..block_end];
+            let left_block = &mut left[block_start..block_end];
+            let right_block = &mut right[block_start..block_end];
             for voice in self.get_voices_mut().iter_mut() {
-                voice.render_block(block);
+                voice.render_block(left_block, right_block);
             }
             block_start = event.offset as usize;
         }
-        let block_end = output.len();
-        let block = &mut output[block_start..block_end];
+        let block_end = left.len();
+        let left_block = &mut left[block_start..block_end];
+        let right_block = &mut right[block_start..block_end];
         for voice in self.get_voices_mut().iter_mut() {
-            voice.render_block(block);
+            voice.render_block(left_block, right_block);
         }
     }
 }
@@ -0,0 +1,95 @@
+use std::path::Path;
+
+/// Reads a WAV file into one buffer of samples per channel.
+/// Returns the channels and the sample rate.
+pub fn read<P: AsRef<Path>>(path: P) -> Result<(Vec<Vec<f32>>, f32), hound::Error> {
+    let mut reader = hound::WavReader::open(path)?;
+    let spec = reader.spec();
+    let interleaved: Vec<f32> = match spec.sample_format {
+        hound::SampleFormat::Float => reader.samples::<f32>().collect::<Result<_, _>>()?,
+        hound::SampleFormat::Int => {
+            let scale = 1.0 / (1_i64 << (spec.bits_per_sample - 1)) as f32;
+            reader
+                .samples::<i32>()
+                .map(|sample| sample.map(|sample| sample as f32 * scale))
+                .collect::<Result<_, _>>()?
+        }
+    };
+    let num_channels = spec.channels as usize;
+    let mut channels = vec![Vec::with_capacity(interleaved.len() / num_channels); num_channels];
+    for frame in interleaved.chunks_exact(num_channels) {
+        for (channel, sample) in channels.iter_mut().zip(frame.iter()) {
+            channel.push(*sample);
+        }
+    }
+    Ok((channels, spec.sample_rate as f32))
+}
+
+/// Writes channels of equal length as a 32 bit float WAV file.
+pub fn write<P: AsRef<Path>>(
+    path: P,
+    channels: &[Vec<f32>],
+    sample_rate: f32,
+) -> Result<(), hound::Error> {
+    let spec = hound::WavSpec {
+        channels: channels.len() as u16,
+        sample_rate: sample_rate as u32,
+        bits_per_sample: 32,
+        sample_format: hound::SampleFormat::Float,
+    };
+    let mut writer = hound::WavWriter::create(path, spec)?;
+    let length = channels.iter().map(|c| c.len()).min().unwrap_or(0);
+    for index in 0..length {
+        for channel in channels {
+            writer.write_sample(channel[index])?;
+        }
+    }
+    writer.finalize()
+}
+
+/// Averages all channels into one.
+pub fn mix_to_mono(channels: &[Vec<f32>]) -> Vec<f32> {
+    let length = channels.iter().map(|c| c.len()).min().unwrap_or(0);
+    let scale = 1.0 / channels.len() as f32;
+    (0..length)
+        .map(|index| channels.iter().map(|c| c[index]).sum::<f32>() * scale)
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_write() {
+        let path = std::env::temp_dir().join("peak_tracker_test_write.wav");
+        let channels = vec![vec![0.5, -0.25], vec![0.125, 1.0]];
+        write(&path, &channels, 48000.0).unwrap();
+        let (read_channels, sample_rate) = read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(sample_rate, 48000.0);
+        assert_eq!(read_channels, channels);
+    }
+
+    #[test]
+    fn test_read() {
+        let path = std::env::temp_dir().join("peak_tracker_test_read.wav");
+        let spec = hound::WavSpec {
+            channels: 2,
+            sample_rate: 44100,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut writer = hound::WavWriter::create(&path, spec).unwrap();
+        for sample in [16384_i16, -16384, 8192, 0] {
+            writer.write_sample(sample).unwrap();
+        }
+        writer.finalize().unwrap();
+
+        let (channels, sample_rate) = read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(sample_rate, 44100.0);
+        assert_eq!(channels, vec![vec![0.5, 0.25], vec![-0.5, 0.0]]);
+        assert_eq!(mix_to_mono(&channels), vec![0.0, 0.125]);
+    }
+}
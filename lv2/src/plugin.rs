@@ -1,6 +1,20 @@
-use core::reconstructor::Reconstructor;
+use core::cross::CrossMode;
+use core::freeze::FreezeMode;
+use core::limiter::LimiterMode;
+use core::osc::Waveform;
+use core::pan::PanMode;
+use core::partial_filter::{Band, Harmonics, PartialFilter};
+use core::quantize::{Quantize, Scale, ScaleKind};
+use core::reconstructor::{InputMode, Reconstructor};
+use core::scala::Tuning;
+use core::smooth::SmoothingCurve;
+use core::snapshot::{SnapshotBank, SnapshotSource, SNAPSHOT_SLOTS};
 use core::voice::{Event, EventData};
 use lv2::prelude::*;
+use std::any::Any;
+use std::ffi::CString;
+use std::io;
+use std::sync::{Arc, Mutex};
 use wmidi::*;
 
 #[derive(FeatureCollection)]
@@ -8,6 +22,11 @@ pub struct Features<'a> {
     map: LV2Map<'a>,
 }
 
+#[derive(FeatureCollection)]
+pub struct AudioFeatures<'a> {
+    schedule: Schedule<'a, ReconstructorPlugin>,
+}
+
 #[derive(PortCollection)]
 struct Ports {
     input: InputPort<Audio>,
@@ -17,55 +36,264 @@ struct Ports {
     detune: InputPort<Control>,
     synth_mode: InputPort<Control>,
     events_in: InputPort<AtomPort>,
+    snapshot_slot: InputPort<Control>,
+    capture: InputPort<Control>,
+    snapshot_mode: InputPort<Control>,
+    morph_from: InputPort<Control>,
+    morph_to: InputPort<Control>,
+    morph: InputPort<Control>,
+    freeze_frames: InputPort<Control>,
+    freeze_mode: InputPort<Control>,
+    freeze_fade: InputPort<Control>,
+    drift_rate: InputPort<Control>,
+    output_right: OutputPort<Audio>,
+    pan_mode: InputPort<Control>,
+    width: InputPort<Control>,
+    input_right: InputPort<Audio>,
+    input_mode: InputPort<Control>,
+    sidechain: InputPort<Audio>,
+    cross_mode: InputPort<Control>,
+    cross_swap: InputPort<Control>,
+    noise_level: InputPort<Control>,
+    transient_mix: InputPort<Control>,
+    mix: InputPort<Control>,
+    latency: OutputPort<Control>,
+    output_gain: InputPort<Control>,
+    limiter_mode: InputPort<Control>,
+    formant_preserve: InputPort<Control>,
+    formant_shift: InputPort<Control>,
+    quantize: InputPort<Control>,
+    quantize_root: InputPort<Control>,
+    quantize_amount: InputPort<Control>,
+    reference_note: InputPort<Control>,
+    frequency_shift: InputPort<Control>,
+    stretch: InputPort<Control>,
+    stretch_pivot: InputPort<Control>,
+    filter_loudest: InputPort<Control>,
+    filter_harmonics: InputPort<Control>,
+    filter_band: InputPort<Control>,
+    band_low: InputPort<Control>,
+    band_high: InputPort<Control>,
+    tilt: InputPort<Control>,
+    filter_depth: InputPort<Control>,
+    frequency_glide: InputPort<Control>,
+    amplitude_glide: InputPort<Control>,
+    amplitude_curve: InputPort<Control>,
+    waveform: InputPort<Control>,
 }
 
+/// The state property holding the captured snapshots.
+#[uri("https://github.com/ctsexton/reconstructor-lv2#snapshots")]
+struct Snapshots;
+
+// The names of the snapshot slots are properties numbered from 1 after this
+const SNAPSHOT_NAME_URI: &str = "https://github.com/ctsexton/reconstructor-lv2#snapshotName";
+
+/// The .scl file of the tuning, set by the host as a path. The state holds
+/// the file's text so that sessions don't depend on the file.
+#[uri("https://github.com/ctsexton/reconstructor-lv2#scale")]
+struct ScaleFile;
+
+/// The .kbm file of the tuning, like `ScaleFile`.
+#[uri("https://github.com/ctsexton/reconstructor-lv2#keyboardMap")]
+struct KeyboardMapFile;
+
+#[uri("http://lv2plug.in/ns/ext/patch#Set")]
+struct PatchSet;
+
+#[uri("http://lv2plug.in/ns/ext/patch#property")]
+struct PatchProperty;
+
+#[uri("http://lv2plug.in/ns/ext/patch#value")]
+struct PatchValue;
+
+#[uri("http://lv2plug.in/ns/ext/atom#Path")]
+struct AtomPath;
+
 #[derive(URIDCollection)]
 struct URIDs {
     atom: AtomURIDCollection,
     midi: MidiURIDCollection,
     units: UnitURIDCollection,
+    snapshots: URID<Snapshots>,
+    scale_file: URID<ScaleFile>,
+    keyboard_map_file: URID<KeyboardMapFile>,
+    patch_set: URID<PatchSet>,
+    patch_property: URID<PatchProperty>,
+    patch_value: URID<PatchValue>,
+    path: URID<AtomPath>,
+}
+
+#[derive(Clone, Copy)]
+enum TuningFile {
+    Scale,
+    KeyboardMap,
+}
+
+/// The texts of the tuning files, empty for the defaults. Shared with the
+/// worker, which reads the files.
+#[derive(Default)]
+struct TuningFiles {
+    scl: String,
+    kbm: String,
+}
+
+impl TuningFiles {
+    fn tuning(&self) -> io::Result<Tuning> {
+        if self.scl.is_empty() {
+            return Ok(Tuning::default());
+        }
+        let kbm = Some(self.kbm.as_str()).filter(|kbm| !kbm.is_empty());
+        Tuning::parse(&self.scl, kbm)
+    }
+}
+
+const MAX_TEXT: usize = 1024;
+
+/// Text copied out of an atom into a fixed buffer, so that handing it to the
+/// worker doesn't allocate.
+struct Text {
+    bytes: [u8; MAX_TEXT],
+    len: usize,
+}
+
+impl Text {
+    /// `None` if the text doesn't fit.
+    fn new(text: &str) -> Option<Self> {
+        let len = text.len();
+        if len > MAX_TEXT {
+            return None;
+        }
+        let mut bytes = [0; MAX_TEXT];
+        bytes[0..len].copy_from_slice(text.as_bytes());
+        Some(Self { bytes, len })
+    }
+
+    fn as_str(&self) -> &str {
+        // Copied from a `str`, so always valid
+        std::str::from_utf8(&self.bytes[0..self.len]).unwrap_or("")
+    }
+}
+
+enum Work {
+    Load {
+        file: TuningFile,
+        path: Text,
+        files: Arc<Mutex<TuningFiles>>,
+    },
+    Name {
+        slot: usize,
+        name: Text,
+        snapshots: Arc<Mutex<SnapshotBank>>,
+    },
+    /// Frees a tuning the audio thread is done with.
+    Drop(Tuning),
+}
+
+/// Reads a tuning file and replaces it in `files` if the tuning it makes
+/// is valid.
+fn load_tuning_file(
+    file: TuningFile,
+    path: &str,
+    files: &Mutex<TuningFiles>,
+) -> io::Result<Tuning> {
+    let text = std::fs::read_to_string(path)?;
+    let mut files = files
+        .lock()
+        .map_err(|_| io::Error::new(io::ErrorKind::Other, "poisoned"))?;
+    let previous = match file {
+        TuningFile::Scale => std::mem::replace(&mut files.scl, text),
+        TuningFile::KeyboardMap => std::mem::replace(&mut files.kbm, text),
+    };
+    let tuning = files.tuning();
+    if tuning.is_err() {
+        match file {
+            TuningFile::Scale => files.scl = previous,
+            TuningFile::KeyboardMap => files.kbm = previous,
+        }
+    }
+    tuning
 }
 
 #[uri("https://github.com/ctsexton/reconstructor-lv2")]
 struct ReconstructorPlugin {
     reconstructor: Reconstructor,
-    input: Vec<f32>,
-    output: Vec<f32>,
+    input: [Vec<f32>; 2],
+    output: [Vec<f32>; 2],
     urids: URIDs,
+    // the property of each slot's name
+    snapshot_names: [URID; SNAPSHOT_SLOTS],
     events: Vec<Event>,
+    capture_held: bool,
+    // The bank the state saves, copied from the reconstructor's after
+    // captures since saving may run at the same time as `run`
+    snapshots: Arc<Mutex<SnapshotBank>>,
+    // Set when a capture still has to be copied into `snapshots`
+    snapshots_dirty: bool,
+    tuning_files: Arc<Mutex<TuningFiles>>,
+    // The scale of the loaded tuning, for quantizing
+    tuning_scale: Option<Scale>,
+}
+
+/// Reads atoms of `urid`'s type as `like`'s type, for atom:Path which has the
+/// same body as atom:String.
+fn with_body_of<T: ?Sized, U: ?Sized>(_like: URID<T>, urid: URID<U>) -> URID<T> {
+    unsafe { URID::new_unchecked(urid.get()) }
+}
+
+// Slot ports are numbered from 1
+fn slot(port: f32) -> usize {
+    (port.round().max(1.0) as usize) - 1
 }
 
 impl Plugin for ReconstructorPlugin {
     type Ports = Ports;
 
     type InitFeatures = Features<'static>;
-    type AudioFeatures = ();
+    type AudioFeatures = AudioFeatures<'static>;
 
     fn new(plugin_info: &PluginInfo, features: &mut Features<'static>) -> Option<Self> {
         let reconstructor = Reconstructor::new(plugin_info.sample_rate() as f32);
-        let input = vec![0_f32; 2048];
-        let output = vec![0_f32; 2048];
+        let input = [vec![0_f32; 2048], vec![0_f32; 2048]];
+        let output = [vec![0_f32; 2048], vec![0_f32; 2048]];
         let events = Vec::<Event>::with_capacity(256);
+        let snapshot_names: [Option<URID>; SNAPSHOT_SLOTS] = std::array::from_fn(|slot| {
+            let uri = CString::new(format!("{}{}", SNAPSHOT_NAME_URI, slot + 1)).ok()?;
+            features.map.map_uri(&uri)
+        });
+        if snapshot_names.contains(&None) {
+            return None;
+        }
+        let snapshot_names = snapshot_names.map(Option::unwrap);
         Some(Self {
             reconstructor,
             input,
             output,
             urids: features.map.populate_collection()?,
+            snapshot_names,
             events,
+            capture_held: false,
+            snapshots: Arc::new(Mutex::new(SnapshotBank::default())),
+            snapshots_dirty: false,
+            tuning_files: Arc::new(Mutex::new(TuningFiles::default())),
+            tuning_scale: Tuning::default().scale(),
         })
     }
 
-    fn run(&mut self, ports: &mut Ports, _features: &mut (), _: u32) {
-        for in_copy in self.input.iter_mut() {
+    fn run(&mut self, ports: &mut Ports, features: &mut AudioFeatures<'static>, _: u32) {
+        for in_copy in self.input.iter_mut().flat_map(|channel| channel.iter_mut()) {
             *in_copy = 0.0;
         }
-        for (in_frame, in_copy) in ports.input.iter().zip(self.input.iter_mut()) {
+        for (in_frame, in_copy) in ports.input.iter().zip(self.input[0].iter_mut()) {
             *in_copy = *in_frame;
         }
-        for out_copy in self.output.iter_mut() {
+        for (in_frame, in_copy) in ports.input_right.iter().zip(self.input[1].iter_mut()) {
+            *in_copy = *in_frame;
+        }
+        for out_copy in self.output.iter_mut().flat_map(|channel| channel.iter_mut()) {
             *out_copy = 0.0;
         }
-        for out_frame in ports.output.iter_mut() {
+        for out_frame in ports.output.iter_mut().chain(ports.output_right.iter_mut()) {
             *out_frame = 0.0;
         }
         self.events.clear();
@@ -84,6 +312,26 @@ impl Plugin for ReconstructorPlugin {
             let message = if let Some(message) = message.read(self.urids.midi.wmidi, ()) {
                 message
             } else {
+                if let Some((header, properties)) = message.read(self.urids.atom.object, ()) {
+                    if header.otype != self.urids.patch_set {
+                        continue;
+                    }
+                    let mut property = None;
+                    let mut value = None;
+                    let path = with_body_of(self.urids.atom.string, self.urids.path);
+                    for (header, atom) in properties {
+                        if header.key == self.urids.patch_property {
+                            property = atom.read(self.urids.atom.urid, ());
+                        } else if header.key == self.urids.patch_value {
+                            value = atom
+                                .read(path, ())
+                                .or_else(|| atom.read(self.urids.atom.string, ()));
+                        }
+                    }
+                    if let (Some(property), Some(value)) = (property, value) {
+                        self.set_property(property, value, &features.schedule);
+                    }
+                }
                 continue;
             };
 
@@ -113,18 +361,287 @@ impl Plugin for ReconstructorPlugin {
 
         let block_size = ports.input.len();
         let freeze_active = *ports.freeze > 0.0;
+        self.reconstructor.set_input_mode(match ports.input_mode.round() as i32 {
+            0 => InputMode::Left,
+            1 => InputMode::Right,
+            3 => InputMode::Side,
+            4 => InputMode::Independent,
+            _ => InputMode::Mid,
+        });
+        self.reconstructor
+            .set_freeze_frames(ports.freeze_frames.round().max(1.0) as usize);
+        self.reconstructor.set_freeze_mode(if *ports.freeze_mode > 0.0 {
+            FreezeMode::Median
+        } else {
+            FreezeMode::Average
+        });
+        self.reconstructor.set_freeze_fade(*ports.freeze_fade / 1000.0);
         self.reconstructor.set_freeze(freeze_active);
         self.reconstructor.set_transpose(*ports.transpose);
+        self.reconstructor.set_formant_preserve(*ports.formant_preserve > 0.0);
+        self.reconstructor.set_formant_shift(*ports.formant_shift);
+        self.reconstructor.set_frequency_shift(*ports.frequency_shift);
+        self.reconstructor.set_stretch(*ports.stretch, *ports.stretch_pivot);
+        self.reconstructor.set_partial_filter(PartialFilter {
+            loudest: ports.filter_loudest.round().max(1.0) as usize,
+            harmonics: match ports.filter_harmonics.round() as i32 {
+                1 => Harmonics::Odd,
+                2 => Harmonics::Even,
+                _ => Harmonics::All,
+            },
+            band: match ports.filter_band.round() as i32 {
+                1 => Band::Inside,
+                2 => Band::Outside,
+                _ => Band::Off,
+            },
+            band_low: *ports.band_low,
+            band_high: *ports.band_high,
+            tilt: *ports.tilt,
+            depth: *ports.filter_depth,
+        });
+        self.reconstructor.set_frequency_glide(*ports.frequency_glide / 1000.0);
+        self.reconstructor.set_amplitude_glide(*ports.amplitude_glide / 1000.0);
+        self.reconstructor.set_amplitude_curve(match ports.amplitude_curve.round() as i32 {
+            1 => SmoothingCurve::Exponential,
+            2 => SmoothingCurve::Decibels,
+            _ => SmoothingCurve::Linear,
+        });
+        self.reconstructor.set_waveform(match ports.waveform.round() as i32 {
+            1 => Waveform::Saw,
+            2 => Waveform::Square,
+            3 => Waveform::Triangle,
+            _ => Waveform::Sine,
+        });
+        let root = ports.quantize_root.max(1.0);
+        let scale = |kind| Quantize::Scale(Scale::new(kind, root));
+        self.reconstructor.set_quantize(match ports.quantize.round() as i32 {
+            1 => scale(ScaleKind::Chromatic),
+            2 => scale(ScaleKind::Major),
+            3 => scale(ScaleKind::Minor),
+            4 => scale(ScaleKind::Pentatonic),
+            5 => Quantize::Harmonics(root),
+            6 => self.tuning_scale.map_or(Quantize::Off, Quantize::Scale),
+            _ => Quantize::Off,
+        });
+        self.reconstructor.set_quantize_amount(*ports.quantize_amount);
         self.reconstructor.set_detune(*ports.detune);
+        self.reconstructor.set_drift_rate(*ports.drift_rate);
+        self.reconstructor.set_noise_level(*ports.noise_level);
+        self.reconstructor.set_transient_mix(*ports.transient_mix);
+        self.reconstructor.set_mix(*ports.mix);
+        self.reconstructor.set_output_gain(*ports.output_gain);
+        self.reconstructor.set_limiter_mode(match ports.limiter_mode.round() as i32 {
+            1 => LimiterMode::SoftClip,
+            2 => LimiterMode::Lookahead,
+            _ => LimiterMode::HardClip,
+        });
+        *ports.latency = self.reconstructor.latency() as f32;
         self.reconstructor.set_synth_mode(*ports.synth_mode > 0.0);
-        self.reconstructor.run(
-            &self.input[0..block_size],
-            &mut self.output[0..block_size],
-            self.events.as_slice(),
+        self.reconstructor
+            .set_reference_note(ports.reference_note.round().clamp(0.0, 127.0) as u8);
+        self.reconstructor.set_snapshot_source(match ports.snapshot_mode.round() as i32 {
+            1 => SnapshotSource::Recall(slot(*ports.snapshot_slot)),
+            2 => SnapshotSource::Morph {
+                from: slot(*ports.morph_from),
+                to: slot(*ports.morph_to),
+                amount: *ports.morph,
+            },
+            _ => SnapshotSource::Live,
+        });
+        self.reconstructor.set_panning(
+            match ports.pan_mode.round() as i32 {
+                1 => PanMode::Frequency,
+                2 => PanMode::Random,
+                3 => PanMode::Alternating,
+                _ => PanMode::Index,
+            },
+            *ports.width,
         );
-        for (out_frame, out_copy) in ports.output.iter_mut().zip(self.output.iter()) {
+        let [left, right] = &mut self.output;
+        let cross_mode = match ports.cross_mode.round() as i32 {
+            1 => CrossMode::Amplitudes,
+            2 => CrossMode::Envelope,
+            _ => CrossMode::Off,
+        };
+        self.reconstructor.set_cross_mode(cross_mode);
+        self.reconstructor.set_cross_swap(*ports.cross_swap > 0.0);
+        let [left_input, right_input] = &self.input;
+        let input = [&left_input[0..block_size], &right_input[0..block_size]];
+        let output = [&mut left[0..block_size], &mut right[0..block_size]];
+        if cross_mode == CrossMode::Off {
+            self.reconstructor.run(input, output, self.events.as_slice());
+        } else {
+            self.reconstructor.run_with_sidechain(
+                input,
+                &ports.sidechain[0..block_size],
+                output,
+                self.events.as_slice(),
+            );
+        }
+        let capture = *ports.capture > 0.0;
+        if capture && !self.capture_held {
+            self.reconstructor.capture_snapshot(slot(*ports.snapshot_slot));
+            self.snapshots_dirty = true;
+        }
+        self.capture_held = capture;
+        if self.snapshots_dirty {
+            // Never block the audio thread, try again next block instead
+            if let Ok(mut snapshots) = self.snapshots.try_lock() {
+                snapshots.copy_snapshots_from(self.reconstructor.snapshot_bank());
+                self.snapshots_dirty = false;
+            }
+        }
+        for (out_frame, out_copy) in ports.output.iter_mut().zip(self.output[0].iter()) {
             *out_frame = *out_copy;
         }
+        for (out_frame, out_copy) in ports.output_right.iter_mut().zip(self.output[1].iter()) {
+            *out_frame = *out_copy;
+        }
+    }
+
+    fn extension_data(uri: &Uri) -> Option<&'static dyn Any> {
+        match_extensions![uri, StateDescriptor<Self>, WorkerDescriptor<Self>]
+    }
+}
+
+impl ReconstructorPlugin {
+    /// Handles a patch:Set of a tuning file or a slot name by handing it to
+    /// the worker, which reads the file or stores the name.
+    fn set_property(&self, property: URID, value: &str, schedule: &Schedule<Self>) {
+        let text = match Text::new(value) {
+            Some(text) => text,
+            None => return,
+        };
+        let work = if property == self.urids.scale_file {
+            Work::Load {
+                file: TuningFile::Scale,
+                path: text,
+                files: self.tuning_files.clone(),
+            }
+        } else if property == self.urids.keyboard_map_file {
+            Work::Load {
+                file: TuningFile::KeyboardMap,
+                path: text,
+                files: self.tuning_files.clone(),
+            }
+        } else if let Some(slot) = self.snapshot_names.iter().position(|urid| *urid == property) {
+            Work::Name {
+                slot,
+                name: text,
+                snapshots: self.snapshots.clone(),
+            }
+        } else {
+            return;
+        };
+        // If the worker is busy the property isn't set, and the host can set
+        // it again
+        let _ = schedule.schedule_work(work);
+    }
+}
+
+impl Worker for ReconstructorPlugin {
+    type WorkData = Work;
+    type ResponseData = Tuning;
+
+    fn work(response_handler: &ResponseHandler<Self>, data: Work) -> Result<(), WorkerError> {
+        match data {
+            Work::Load { file, path, files } => {
+                // An invalid file leaves the current tuning playing
+                let tuning = load_tuning_file(file, path.as_str(), &files)
+                    .map_err(|_| WorkerError::Unknown)?;
+                response_handler
+                    .respond(tuning)
+                    .map_err(|_| WorkerError::NoSpace)
+            }
+            Work::Name {
+                slot,
+                name,
+                snapshots,
+            } => {
+                let mut snapshots = snapshots.lock().map_err(|_| WorkerError::Unknown)?;
+                snapshots.set_name(slot, name.as_str().to_string());
+                Ok(())
+            }
+            Work::Drop(_) => Ok(()),
+        }
+    }
+
+    fn work_response(
+        &mut self,
+        tuning: Tuning,
+        features: &mut AudioFeatures<'static>,
+    ) -> Result<(), WorkerError> {
+        self.reconstructor.set_tuning(&tuning);
+        self.tuning_scale = tuning.scale();
+        // Only dropped here if the worker is busy
+        let _ = features.schedule.schedule_work(Work::Drop(tuning));
+        Ok(())
+    }
+}
+
+impl State for ReconstructorPlugin {
+    type StateFeatures = ();
+
+    fn save(&self, mut store: StoreHandle, _: ()) -> Result<(), StateErr> {
+        let snapshots = self.snapshots.lock().map_err(|_| StateErr::Unknown)?;
+        store
+            .draft(self.urids.snapshots)
+            .init(self.urids.atom.vector(), self.urids.atom.float)?
+            .append(&snapshots.to_floats())
+            .ok_or(StateErr::NoSpace)?;
+        for (slot, key) in self.snapshot_names.iter().enumerate() {
+            store
+                .draft(*key)
+                .init(self.urids.atom.string, ())?
+                .append(snapshots.name(slot))
+                .ok_or(StateErr::NoSpace)?;
+        }
+        let files = self.tuning_files.lock().map_err(|_| StateErr::Unknown)?;
+        let texts = [
+            (self.urids.scale_file.into_general(), &files.scl),
+            (self.urids.keyboard_map_file.into_general(), &files.kbm),
+        ];
+        for (key, text) in texts {
+            store
+                .draft(key)
+                .init(self.urids.atom.string, ())?
+                .append(text)
+                .ok_or(StateErr::NoSpace)?;
+        }
+        store.commit_all()
+    }
+
+    fn restore(&mut self, store: RetrieveHandle, _: ()) -> Result<(), StateErr> {
+        // Sessions saved before snapshots were stored have none
+        if let Ok(property) = store.retrieve(self.urids.snapshots) {
+            let snapshots = property.read(self.urids.atom.vector(), self.urids.atom.float)?;
+            let mut bank = SnapshotBank::from_floats(snapshots).ok_or(StateErr::BadData)?;
+            // Slots without a saved name keep the default one
+            for (slot, key) in self.snapshot_names.iter().enumerate() {
+                if let Ok(property) = store.retrieve(*key) {
+                    bank.set_name(slot, property.read(self.urids.atom.string, ())?.to_string());
+                }
+            }
+            self.reconstructor.set_snapshot_bank(bank.clone());
+            *self.snapshots.lock().map_err(|_| StateErr::Unknown)? = bank;
+            self.snapshots_dirty = false;
+        }
+        // Without tuning files the tuning is equal temperament
+        let mut files = TuningFiles::default();
+        let texts = [
+            (self.urids.scale_file.into_general(), &mut files.scl),
+            (self.urids.keyboard_map_file.into_general(), &mut files.kbm),
+        ];
+        for (key, text) in texts {
+            if let Ok(property) = store.retrieve(key) {
+                *text = property.read(self.urids.atom.string, ())?.to_string();
+            }
+        }
+        let tuning = files.tuning().map_err(|_| StateErr::BadData)?;
+        self.reconstructor.set_tuning(&tuning);
+        self.tuning_scale = tuning.scale();
+        *self.tuning_files.lock().map_err(|_| StateErr::Unknown)? = files;
+        Ok(())
     }
 }
 
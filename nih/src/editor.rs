@@ -0,0 +1,148 @@
+use crate::osc_output::OscOutput;
+use crate::wavetable_input::WavetableInput;
+use crate::PeakTrackerParams;
+use core::scala::Tuning;
+use core::snapshot::SNAPSHOT_SLOTS;
+use core::wav;
+use nih_plug::prelude::*;
+use nih_plug_egui::egui;
+use nih_plug_egui::widgets::generic_ui::{self, GenericSlider};
+use nih_plug_egui::{create_egui_editor, EguiState};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+pub fn default_state() -> Arc<EguiState> {
+    EguiState::from_size(520, 720)
+}
+
+struct EditorState {
+    osc_target: String,
+    scl_path: String,
+    // empty for the default mapping
+    kbm_path: String,
+    snapshot_names: [String; SNAPSHOT_SLOTS],
+    wavetable_path: String,
+    // The last error, shown until the next action
+    message: Option<String>,
+}
+
+fn set_tuning(params: &PeakTrackerParams, tuning_changed: &AtomicBool, tuning: Tuning) {
+    if let Ok(mut current) = params.tuning.write() {
+        *current = tuning;
+    }
+    tuning_changed.store(true, Ordering::Release);
+}
+
+/// The parameters plus the settings that don't fit in a parameter.
+/// `tuning_changed` tells the audio thread to pick up a newly loaded tuning.
+pub fn create(
+    params: Arc<PeakTrackerParams>,
+    osc: Arc<OscOutput>,
+    wavetable: Arc<WavetableInput>,
+    tuning_changed: Arc<AtomicBool>,
+) -> Option<Box<dyn Editor>> {
+    let osc_target = params
+        .osc_target
+        .read()
+        .map(|target| target.clone())
+        .unwrap_or_default();
+    let snapshot_names = match params.snapshots.read() {
+        Ok(snapshots) => std::array::from_fn(|slot| snapshots.name(slot).to_string()),
+        Err(_) => Default::default(),
+    };
+    create_egui_editor(
+        params.editor_state.clone(),
+        EditorState {
+            osc_target,
+            scl_path: String::new(),
+            kbm_path: String::new(),
+            snapshot_names,
+            wavetable_path: String::new(),
+            message: None,
+        },
+        |_, _| {},
+        move |egui_ctx, setter, state| {
+            egui::CentralPanel::default().show(egui_ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("OSC Target");
+                    ui.text_edit_singleline(&mut state.osc_target);
+                    if ui.button("Send").clicked() {
+                        state.message = osc.start(&state.osc_target).err().map(|err| err.to_string());
+                        if state.message.is_none() {
+                            if let Ok(mut target) = params.osc_target.write() {
+                                target.clone_from(&state.osc_target);
+                            }
+                        }
+                    }
+                    if ui.button("Stop").clicked() {
+                        osc.stop();
+                        state.message = None;
+                        if let Ok(mut target) = params.osc_target.write() {
+                            target.clear();
+                        }
+                    }
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Scale (.scl)");
+                    ui.text_edit_singleline(&mut state.scl_path);
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Keyboard Map (.kbm)");
+                    ui.text_edit_singleline(&mut state.kbm_path);
+                });
+                ui.horizontal(|ui| {
+                    if ui.button("Load Tuning").clicked() {
+                        let kbm = Some(state.kbm_path.as_str()).filter(|path| !path.is_empty());
+                        match Tuning::load(state.scl_path.as_str(), kbm) {
+                            Ok(tuning) => {
+                                set_tuning(&params, &tuning_changed, tuning);
+                                state.message = None;
+                            }
+                            Err(err) => state.message = Some(err.to_string()),
+                        }
+                    }
+                    if ui.button("Equal Temperament").clicked() {
+                        set_tuning(&params, &tuning_changed, Tuning::default());
+                        state.message = None;
+                    }
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Wavetable (.wav)");
+                    ui.text_edit_singleline(&mut state.wavetable_path);
+                    if ui.button("Load").clicked() {
+                        match wav::read(&state.wavetable_path) {
+                            Ok((channels, _)) => {
+                                let cycle = wav::mix_to_mono(&channels);
+                                wavetable.load(&cycle);
+                                if let Ok(mut current) = params.wavetable.write() {
+                                    *current = cycle;
+                                }
+                                state.message = None;
+                            }
+                            Err(err) => state.message = Some(err.to_string()),
+                        }
+                    }
+                });
+                ui.collapsing("Snapshot Names", |ui| {
+                    for (slot, name) in state.snapshot_names.iter_mut().enumerate() {
+                        ui.horizontal(|ui| {
+                            ui.label(format!("Slot {}", slot + 1));
+                            if ui.text_edit_singleline(name).changed() {
+                                if let Ok(mut snapshots) = params.snapshots.write() {
+                                    snapshots.set_name(slot, name.clone());
+                                }
+                            }
+                        });
+                    }
+                });
+                if let Some(message) = &state.message {
+                    ui.label(message);
+                }
+                ui.separator();
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    generic_ui::create(ui, params.clone(), setter, GenericSlider);
+                });
+            });
+        },
+    )
+}
@@ -1,14 +1,133 @@
 use nih_plug::prelude::*;
-use std::sync::Arc;
-use core::reconstructor::Reconstructor;
+use nih_plug_egui::EguiState;
+use osc_output::OscOutput;
+use wavetable_input::WavetableInput;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use core::cross::CrossMode;
+use core::freeze::{FreezeMode, MAX_FREEZE_FRAMES};
+use core::limiter::LimiterMode;
+use core::osc::Waveform;
+use core::pan::PanMode;
+use core::partial_filter::{Band, Harmonics, PartialFilter};
+use core::peak::MAX_PEAKS;
+use core::quantize::{Quantize, Scale, ScaleKind};
+use core::reconstructor::{InputMode, Reconstructor};
+use core::scala::Tuning;
+use core::smooth::SmoothingCurve;
+use core::snapshot::{SnapshotBank, SnapshotSource, SNAPSHOT_SLOTS};
 use core::voice::{Event, EventData};
 
+mod editor;
+mod osc_output;
+mod wavetable_input;
+
+// The reconstructor's own glide of 64 samples at 48 kHz, in ms
+const DEFAULT_GLIDE: f32 = 64.0 / 48.0;
+
 struct PeakTracker {
     params: Arc<PeakTrackerParams>,
     reconstructor: Option<Reconstructor>,
-    input: Vec<f32>,
-    output: Vec<f32>,
+    input: [Vec<f32>; 2],
+    // with a mono input the first channel is analyzed as both sides
+    stereo_input: bool,
+    sidechain: Vec<f32>,
+    output: [Vec<f32>; 2],
     events: Vec<Event>,
+    capture_held: bool,
+    // Set when a capture still has to be copied into the persisted bank
+    snapshots_dirty: bool,
+    // The scale of the loaded tuning, for quantizing
+    tuning_scale: Option<Scale>,
+    // Set by the editor when it loads a tuning
+    tuning_changed: Arc<AtomicBool>,
+    osc: Arc<OscOutput>,
+    wavetable: Arc<WavetableInput>,
+}
+
+#[derive(Enum, Debug, PartialEq, Clone, Copy)]
+enum FreezeCapture {
+    Average,
+    Median,
+}
+
+#[derive(Enum, Debug, PartialEq, Clone, Copy)]
+enum Input {
+    Left,
+    Right,
+    Mid,
+    Side,
+    Independent,
+}
+
+#[derive(Enum, Debug, PartialEq, Clone, Copy)]
+enum Cross {
+    Off,
+    Amplitudes,
+    Envelope,
+}
+
+#[derive(Enum, Debug, PartialEq, Clone, Copy)]
+enum Limit {
+    HardClip,
+    SoftClip,
+    Lookahead,
+}
+
+#[derive(Enum, Debug, PartialEq, Clone, Copy)]
+enum HarmonicFilter {
+    All,
+    Odd,
+    Even,
+}
+
+#[derive(Enum, Debug, PartialEq, Clone, Copy)]
+enum BandFilter {
+    Off,
+    Inside,
+    Outside,
+}
+
+#[derive(Enum, Debug, PartialEq, Clone, Copy)]
+enum GlideCurve {
+    Linear,
+    Exponential,
+    Decibels,
+}
+
+#[derive(Enum, Debug, PartialEq, Clone, Copy)]
+enum Shape {
+    Sine,
+    Saw,
+    Square,
+    Triangle,
+    Wavetable,
+}
+
+#[derive(Enum, Debug, PartialEq, Clone, Copy)]
+enum Quantization {
+    Off,
+    Chromatic,
+    Major,
+    Minor,
+    Pentatonic,
+    Harmonics,
+    Tuning,
+}
+
+#[derive(Enum, Debug, PartialEq, Clone, Copy)]
+enum Pan {
+    Index,
+    Frequency,
+    Random,
+    Alternating,
+}
+
+#[derive(Enum, Debug, PartialEq, Clone, Copy)]
+enum SnapshotMode {
+    Live,
+    Recall,
+    Morph,
 }
 
 #[derive(Params)]
@@ -17,14 +136,113 @@ struct PeakTrackerParams {
     /// these IDs remain constant, you can rename and reorder these fields as you wish. The
     /// parameters are exposed to the host in the same order they were defined. In this case, this
     /// gain parameter is stored as linear gain while the values are displayed in decibels.
+    #[id = "input_mode"]
+    pub input_mode: EnumParam<Input>,
+    #[id = "cross_mode"]
+    pub cross_mode: EnumParam<Cross>,
+    #[id = "cross_swap"]
+    pub cross_swap: BoolParam,
     #[id = "freeze"]
     pub freeze: BoolParam,
+    #[id = "freeze_frames"]
+    pub freeze_frames: IntParam,
+    #[id = "freeze_capture"]
+    pub freeze_capture: EnumParam<FreezeCapture>,
+    #[id = "freeze_fade"]
+    pub freeze_fade: FloatParam,
     #[id = "transpose"]
     pub transpose: FloatParam,
+    #[id = "formant_preserve"]
+    pub formant_preserve: BoolParam,
+    #[id = "formant_shift"]
+    pub formant_shift: FloatParam,
+    #[id = "frequency_shift"]
+    pub frequency_shift: FloatParam,
+    #[id = "stretch"]
+    pub stretch: FloatParam,
+    #[id = "stretch_pivot"]
+    pub stretch_pivot: FloatParam,
+    #[id = "filter_loudest"]
+    pub filter_loudest: IntParam,
+    #[id = "filter_harmonics"]
+    pub filter_harmonics: EnumParam<HarmonicFilter>,
+    #[id = "filter_band"]
+    pub filter_band: EnumParam<BandFilter>,
+    #[id = "band_low"]
+    pub band_low: FloatParam,
+    #[id = "band_high"]
+    pub band_high: FloatParam,
+    #[id = "tilt"]
+    pub tilt: FloatParam,
+    #[id = "filter_depth"]
+    pub filter_depth: FloatParam,
+    #[id = "frequency_glide"]
+    pub frequency_glide: FloatParam,
+    #[id = "amplitude_glide"]
+    pub amplitude_glide: FloatParam,
+    #[id = "amplitude_curve"]
+    pub amplitude_curve: EnumParam<GlideCurve>,
+    #[id = "waveform"]
+    pub waveform: EnumParam<Shape>,
+    #[id = "quantize"]
+    pub quantize: EnumParam<Quantization>,
+    #[id = "quantize_root"]
+    pub quantize_root: FloatParam,
+    #[id = "quantize_amount"]
+    pub quantize_amount: FloatParam,
     #[id = "detune"]
     pub detune: FloatParam,
+    #[id = "drift_rate"]
+    pub drift_rate: FloatParam,
+    #[id = "noise_level"]
+    pub noise_level: FloatParam,
+    #[id = "transient_mix"]
+    pub transient_mix: FloatParam,
+    #[id = "mix"]
+    pub mix: FloatParam,
+    #[id = "output_gain"]
+    pub output_gain: FloatParam,
+    #[id = "limiter_mode"]
+    pub limiter_mode: EnumParam<Limit>,
     #[id = "synth_mode"]
     pub synth_mode: BoolParam,
+    #[id = "reference_note"]
+    pub reference_note: IntParam,
+    #[id = "pan_mode"]
+    pub pan_mode: EnumParam<Pan>,
+    #[id = "width"]
+    pub width: FloatParam,
+    #[id = "snapshot_slot"]
+    pub snapshot_slot: IntParam,
+    #[id = "capture"]
+    pub capture: BoolParam,
+    #[id = "snapshot_mode"]
+    pub snapshot_mode: EnumParam<SnapshotMode>,
+    #[id = "morph_from"]
+    pub morph_from: IntParam,
+    #[id = "morph_to"]
+    pub morph_to: IntParam,
+    #[id = "morph"]
+    pub morph: FloatParam,
+
+    /// Captured spectra are saved with the plugin state.
+    #[persist = "snapshots"]
+    pub snapshots: Arc<RwLock<SnapshotBank>>,
+
+    /// The Scala tuning of synth mode, saved with the plugin state.
+    #[persist = "tuning"]
+    pub tuning: Arc<RwLock<Tuning>>,
+
+    /// The single cycle of the wavetable waveform, empty for none.
+    #[persist = "wavetable"]
+    pub wavetable: Arc<RwLock<Vec<f32>>>,
+
+    /// Where the peaks are sent over OSC as "host:port", empty for nowhere.
+    #[persist = "osc-target"]
+    pub osc_target: Arc<RwLock<String>>,
+
+    #[persist = "editor-state"]
+    editor_state: Arc<EguiState>,
 }
 
 impl Default for PeakTracker {
@@ -32,9 +250,17 @@ impl Default for PeakTracker {
         Self {
             params: Arc::new(PeakTrackerParams::default()),
             reconstructor: None,
-            input: vec![0_f32; 4096],
-            output: vec![0_f32; 4096],
+            input: [vec![0_f32; 4096], vec![0_f32; 4096]],
+            stereo_input: false,
+            sidechain: vec![0_f32; 4096],
+            output: [vec![0_f32; 4096], vec![0_f32; 4096]],
             events: Vec::<Event>::with_capacity(256),
+            capture_held: false,
+            snapshots_dirty: false,
+            tuning_scale: None,
+            tuning_changed: Arc::new(AtomicBool::new(false)),
+            osc: Arc::new(OscOutput::default()),
+            wavetable: Arc::new(WavetableInput::default()),
         }
     }
 }
@@ -42,10 +268,43 @@ impl Default for PeakTracker {
 impl Default for PeakTrackerParams {
     fn default() -> Self {
         Self {
+            input_mode: EnumParam::new(
+                "Input Mode",
+                Input::Mid,
+            ),
+            cross_mode: EnumParam::new(
+                "Cross-Synthesis",
+                Cross::Off,
+            ),
+            cross_swap: BoolParam::new(
+                "Frequencies From Sidechain",
+                false,
+            ),
             freeze: BoolParam::new(
                 "Freeze",
                 false,
             ),
+            freeze_frames: IntParam::new(
+                "Freeze Frames",
+                1,
+                IntRange::Linear {
+                    min: 1,
+                    max: MAX_FREEZE_FRAMES as i32,
+                },
+            ),
+            freeze_capture: EnumParam::new(
+                "Freeze Capture",
+                FreezeCapture::Average,
+            ),
+            freeze_fade: FloatParam::new(
+                "Freeze Fade",
+                0.0,
+                FloatRange::Linear {
+                    min: 0.0,
+                    max: 2000.0,
+                },
+            )
+            .with_unit(" ms"),
             transpose: FloatParam::new(
                 "Transpose",
                 0.0,
@@ -54,6 +313,149 @@ impl Default for PeakTrackerParams {
                     max: 2.0,
                 },
             ),
+            formant_preserve: BoolParam::new(
+                "Preserve Formants",
+                false,
+            ),
+            formant_shift: FloatParam::new(
+                "Formant Shift",
+                0.0,
+                FloatRange::Linear {
+                    min: -2.0,
+                    max: 2.0,
+                },
+            ),
+            frequency_shift: FloatParam::new(
+                "Frequency Shift",
+                0.0,
+                FloatRange::Linear {
+                    min: -1000.0,
+                    max: 1000.0,
+                },
+            )
+            .with_unit(" Hz"),
+            stretch: FloatParam::new(
+                "Stretch",
+                1.0,
+                FloatRange::Skewed {
+                    min: 0.25,
+                    max: 4.0,
+                    factor: FloatRange::skew_factor(-1.0),
+                },
+            ),
+            stretch_pivot: FloatParam::new(
+                "Stretch Pivot",
+                261.63,
+                FloatRange::Skewed {
+                    min: 20.0,
+                    max: 2000.0,
+                    factor: FloatRange::skew_factor(-2.0),
+                },
+            )
+            .with_unit(" Hz"),
+            filter_loudest: IntParam::new(
+                "Loudest Partials",
+                MAX_PEAKS as i32,
+                IntRange::Linear {
+                    min: 1,
+                    max: MAX_PEAKS as i32,
+                },
+            ),
+            filter_harmonics: EnumParam::new(
+                "Harmonics",
+                HarmonicFilter::All,
+            ),
+            filter_band: EnumParam::new(
+                "Band",
+                BandFilter::Off,
+            ),
+            band_low: FloatParam::new(
+                "Band Low",
+                200.0,
+                FloatRange::Skewed {
+                    min: 20.0,
+                    max: 20000.0,
+                    factor: FloatRange::skew_factor(-2.0),
+                },
+            )
+            .with_unit(" Hz"),
+            band_high: FloatParam::new(
+                "Band High",
+                2000.0,
+                FloatRange::Skewed {
+                    min: 20.0,
+                    max: 20000.0,
+                    factor: FloatRange::skew_factor(-2.0),
+                },
+            )
+            .with_unit(" Hz"),
+            tilt: FloatParam::new(
+                "Tilt",
+                0.0,
+                FloatRange::Linear {
+                    min: -12.0,
+                    max: 12.0,
+                },
+            )
+            .with_unit(" dB/oct"),
+            filter_depth: FloatParam::new(
+                "Filter Depth",
+                1.0,
+                FloatRange::Linear {
+                    min: 0.0,
+                    max: 1.0,
+                },
+            ),
+            frequency_glide: FloatParam::new(
+                "Frequency Glide",
+                DEFAULT_GLIDE,
+                FloatRange::Skewed {
+                    min: 1.0,
+                    max: 5000.0,
+                    factor: FloatRange::skew_factor(-2.0),
+                },
+            )
+            .with_unit(" ms"),
+            amplitude_glide: FloatParam::new(
+                "Amplitude Glide",
+                DEFAULT_GLIDE,
+                FloatRange::Skewed {
+                    min: 1.0,
+                    max: 5000.0,
+                    factor: FloatRange::skew_factor(-2.0),
+                },
+            )
+            .with_unit(" ms"),
+            amplitude_curve: EnumParam::new(
+                "Amplitude Curve",
+                GlideCurve::Linear,
+            ),
+            waveform: EnumParam::new(
+                "Waveform",
+                Shape::Sine,
+            ),
+            quantize: EnumParam::new(
+                "Quantize",
+                Quantization::Off,
+            ),
+            quantize_root: FloatParam::new(
+                "Quantize Root",
+                261.63,
+                FloatRange::Skewed {
+                    min: 20.0,
+                    max: 2000.0,
+                    factor: FloatRange::skew_factor(-2.0),
+                },
+            )
+            .with_unit(" Hz"),
+            quantize_amount: FloatParam::new(
+                "Quantize Amount",
+                1.0,
+                FloatRange::Linear {
+                    min: 0.0,
+                    max: 1.0,
+                },
+            ),
             detune: FloatParam::new(
                 "Detune",
                 0.0,
@@ -62,10 +464,122 @@ impl Default for PeakTrackerParams {
                     max: 1.0,
                 },
             ),
+            drift_rate: FloatParam::new(
+                "Drift Rate",
+                0.0,
+                FloatRange::Skewed {
+                    min: 0.0,
+                    max: 20.0,
+                    factor: FloatRange::skew_factor(-2.0),
+                },
+            )
+            .with_unit(" Hz"),
+            noise_level: FloatParam::new(
+                "Noise Level",
+                0.0,
+                FloatRange::Linear {
+                    min: 0.0,
+                    max: 1.0,
+                },
+            ),
+            transient_mix: FloatParam::new(
+                "Transient Mix",
+                0.0,
+                FloatRange::Linear {
+                    min: 0.0,
+                    max: 1.0,
+                },
+            ),
+            mix: FloatParam::new(
+                "Mix",
+                1.0,
+                FloatRange::Linear {
+                    min: 0.0,
+                    max: 1.0,
+                },
+            ),
+            output_gain: FloatParam::new(
+                "Output Gain",
+                0.0,
+                FloatRange::Linear {
+                    min: -24.0,
+                    max: 12.0,
+                },
+            )
+            .with_unit(" dB"),
+            limiter_mode: EnumParam::new(
+                "Limiter",
+                Limit::HardClip,
+            ),
             synth_mode: BoolParam::new(
                 "Synth Mode",
                 false,
             ),
+            reference_note: IntParam::new(
+                "Reference Note",
+                60,
+                IntRange::Linear {
+                    min: 0,
+                    max: 127,
+                },
+            ),
+            pan_mode: EnumParam::new(
+                "Pan Mode",
+                Pan::Index,
+            ),
+            width: FloatParam::new(
+                "Width",
+                0.0,
+                FloatRange::Linear {
+                    min: 0.0,
+                    max: 1.0,
+                },
+            ),
+            snapshot_slot: IntParam::new(
+                "Snapshot Slot",
+                1,
+                IntRange::Linear {
+                    min: 1,
+                    max: SNAPSHOT_SLOTS as i32,
+                },
+            ),
+            capture: BoolParam::new(
+                "Capture Snapshot",
+                false,
+            ),
+            snapshot_mode: EnumParam::new(
+                "Snapshot Mode",
+                SnapshotMode::Live,
+            ),
+            morph_from: IntParam::new(
+                "Morph From",
+                1,
+                IntRange::Linear {
+                    min: 1,
+                    max: SNAPSHOT_SLOTS as i32,
+                },
+            ),
+            morph_to: IntParam::new(
+                "Morph To",
+                2,
+                IntRange::Linear {
+                    min: 1,
+                    max: SNAPSHOT_SLOTS as i32,
+                },
+            ),
+            morph: FloatParam::new(
+                "Morph",
+                0.0,
+                FloatRange::Linear {
+                    min: 0.0,
+                    max: 1.0,
+                },
+            ),
+            snapshots: Arc::new(RwLock::new(SnapshotBank::default())),
+            tuning: Arc::new(RwLock::new(Tuning::default())),
+            wavetable: Arc::new(RwLock::new(Vec::new())),
+            osc_target: Arc::new(RwLock::new(String::new())),
+            editor_state: editor::default_state(),
         }
     }
 }
@@ -80,18 +594,33 @@ impl Plugin for PeakTracker {
 
     // The first audio IO layout is used as the default. The other layouts may be selected either
     // explicitly or automatically by the host or the user depending on the plugin API/backend.
-    const AUDIO_IO_LAYOUTS: &'static [AudioIOLayout] = &[AudioIOLayout {
-        main_input_channels: NonZeroU32::new(1),
-        main_output_channels: NonZeroU32::new(1),
+    const AUDIO_IO_LAYOUTS: &'static [AudioIOLayout] = &[
+        AudioIOLayout {
+            main_input_channels: NonZeroU32::new(2),
+            main_output_channels: NonZeroU32::new(2),
 
-        aux_input_ports: &[],
-        aux_output_ports: &[],
+            // The sidechain for cross-synthesis
+            aux_input_ports: &[new_nonzero_u32(1)],
+            aux_output_ports: &[],
 
-        // Individual ports and the layout as a whole can be named here. By default these names
-        // are generated as needed. This layout will be called 'Stereo', while a layout with
-        // only one input and output channel would be called 'Mono'.
-        names: PortNames::const_default(),
-    }];
+            // Individual ports and the layout as a whole can be named here. By default these names
+            // are generated as needed. This layout will be called 'Stereo', while a layout with
+            // only one input and output channel would be called 'Mono'.
+            names: PortNames::const_default(),
+        },
+        AudioIOLayout {
+            main_input_channels: NonZeroU32::new(1),
+            main_output_channels: NonZeroU32::new(2),
+            aux_input_ports: &[new_nonzero_u32(1)],
+            ..AudioIOLayout::const_default()
+        },
+        AudioIOLayout {
+            main_input_channels: NonZeroU32::new(1),
+            main_output_channels: NonZeroU32::new(1),
+            aux_input_ports: &[new_nonzero_u32(1)],
+            ..AudioIOLayout::const_default()
+        },
+    ];
 
 
     const MIDI_INPUT: MidiConfig = MidiConfig::Basic;
@@ -112,13 +641,45 @@ impl Plugin for PeakTracker {
         self.params.clone()
     }
 
+    fn editor(&mut self, _async_executor: AsyncExecutor<Self>) -> Option<Box<dyn Editor>> {
+        editor::create(
+            self.params.clone(),
+            self.osc.clone(),
+            self.wavetable.clone(),
+            self.tuning_changed.clone(),
+        )
+    }
+
     fn initialize(
         &mut self,
-        _audio_io_layout: &AudioIOLayout,
+        audio_io_layout: &AudioIOLayout,
         buffer_config: &BufferConfig,
-        _context: &mut impl InitContext<Self>,
+        context: &mut impl InitContext<Self>,
     ) -> bool {
-        self.reconstructor = Some(Reconstructor::new(buffer_config.sample_rate));
+        self.stereo_input = audio_io_layout
+            .main_input_channels
+            .is_some_and(|channels| channels.get() >= 2);
+        let mut reconstructor = Reconstructor::new(buffer_config.sample_rate);
+        if let Ok(snapshots) = self.params.snapshots.read() {
+            reconstructor.set_snapshot_bank(snapshots.clone());
+        }
+        if let Ok(tuning) = self.params.tuning.read() {
+            reconstructor.set_tuning(&tuning);
+            self.tuning_scale = tuning.scale();
+        }
+        if let Ok(wavetable) = self.params.wavetable.read() {
+            reconstructor.set_wavetable(&wavetable);
+        }
+        if let Ok(target) = self.params.osc_target.read() {
+            if !target.is_empty() {
+                if let Err(err) = self.osc.start(&target) {
+                    nih_log!("Can't send OSC to {}: {}", target, err);
+                }
+            }
+        }
+        self.osc.update(&mut reconstructor);
+        context.set_latency_samples(reconstructor.latency() as u32);
+        self.reconstructor = Some(reconstructor);
         true
     }
 
@@ -130,17 +691,24 @@ impl Plugin for PeakTracker {
     fn process(
         &mut self,
         buffer: &mut Buffer,
-        _aux: &mut AuxiliaryBuffers,
+        aux: &mut AuxiliaryBuffers,
         context: &mut impl ProcessContext<Self>,
     ) -> ProcessStatus {
-        for in_copy in self.input.iter_mut() {
+        for in_copy in self.input.iter_mut().flat_map(|channel| channel.iter_mut()) {
             *in_copy = 0.0;
         }
-        let input_channel = buffer.as_slice().first().unwrap();
-        for (sample, in_copy) in input_channel.iter().zip(self.input.iter_mut()) {
-            *in_copy = *sample;
+        let input_channels = if self.stereo_input { 2 } else { 1 };
+        for (index, in_copy) in self.input.iter_mut().enumerate() {
+            let input_channel = &buffer.as_slice()[index.min(input_channels - 1)];
+            in_copy[0..input_channel.len()].copy_from_slice(input_channel);
+        }
+        for side_copy in self.sidechain.iter_mut() {
+            *side_copy = 0.0;
         }
-        for out_copy in self.output.iter_mut() {
+        if let Some(sidechain) = aux.inputs.first_mut().and_then(|aux| aux.as_slice().first()) {
+            self.sidechain[0..sidechain.len()].copy_from_slice(sidechain);
+        }
+        for out_copy in self.output.iter_mut().flat_map(|channel| channel.iter_mut()) {
             *out_copy = 0.0;
         }
         for channel_samples in buffer.iter_samples() {
@@ -181,21 +749,166 @@ impl Plugin for PeakTracker {
         }
 
         let mut reconstructor = self.reconstructor.take().unwrap();
+        self.osc.update(&mut reconstructor);
+        self.wavetable.update(&mut reconstructor);
+        // The editor holds the lock only while it swaps in a new tuning, so
+        // this just tries again next block then
+        if self.tuning_changed.load(Ordering::Acquire) {
+            if let Ok(tuning) = self.params.tuning.try_read() {
+                reconstructor.set_tuning(&tuning);
+                self.tuning_scale = tuning.scale();
+                self.tuning_changed.store(false, Ordering::Release);
+            }
+        }
+        reconstructor.set_input_mode(match self.params.input_mode.value() {
+            Input::Left => InputMode::Left,
+            Input::Right => InputMode::Right,
+            Input::Mid => InputMode::Mid,
+            Input::Side => InputMode::Side,
+            Input::Independent => InputMode::Independent,
+        });
+        reconstructor.set_freeze_frames(self.params.freeze_frames.value() as usize);
+        reconstructor.set_freeze_mode(match self.params.freeze_capture.value() {
+            FreezeCapture::Average => FreezeMode::Average,
+            FreezeCapture::Median => FreezeMode::Median,
+        });
+        reconstructor.set_freeze_fade(self.params.freeze_fade.value() / 1000.0);
         reconstructor.set_freeze(self.params.freeze.value());
         reconstructor.set_transpose(self.params.transpose.value());
+        reconstructor.set_formant_preserve(self.params.formant_preserve.value());
+        reconstructor.set_formant_shift(self.params.formant_shift.value());
+        reconstructor.set_frequency_shift(self.params.frequency_shift.value());
+        reconstructor.set_stretch(self.params.stretch.value(), self.params.stretch_pivot.value());
+        reconstructor.set_partial_filter(PartialFilter {
+            loudest: self.params.filter_loudest.value() as usize,
+            harmonics: match self.params.filter_harmonics.value() {
+                HarmonicFilter::All => Harmonics::All,
+                HarmonicFilter::Odd => Harmonics::Odd,
+                HarmonicFilter::Even => Harmonics::Even,
+            },
+            band: match self.params.filter_band.value() {
+                BandFilter::Off => Band::Off,
+                BandFilter::Inside => Band::Inside,
+                BandFilter::Outside => Band::Outside,
+            },
+            band_low: self.params.band_low.value(),
+            band_high: self.params.band_high.value(),
+            tilt: self.params.tilt.value(),
+            depth: self.params.filter_depth.value(),
+        });
+        reconstructor.set_frequency_glide(self.params.frequency_glide.value() / 1000.0);
+        reconstructor.set_amplitude_glide(self.params.amplitude_glide.value() / 1000.0);
+        reconstructor.set_amplitude_curve(match self.params.amplitude_curve.value() {
+            GlideCurve::Linear => SmoothingCurve::Linear,
+            GlideCurve::Exponential => SmoothingCurve::Exponential,
+            GlideCurve::Decibels => SmoothingCurve::Decibels,
+        });
+        reconstructor.set_waveform(match self.params.waveform.value() {
+            Shape::Sine => Waveform::Sine,
+            Shape::Saw => Waveform::Saw,
+            Shape::Square => Waveform::Square,
+            Shape::Triangle => Waveform::Triangle,
+            Shape::Wavetable => Waveform::Wavetable,
+        });
+        let root = self.params.quantize_root.value();
+        let scale = |kind| Quantize::Scale(Scale::new(kind, root));
+        reconstructor.set_quantize(match self.params.quantize.value() {
+            Quantization::Off => Quantize::Off,
+            Quantization::Chromatic => scale(ScaleKind::Chromatic),
+            Quantization::Major => scale(ScaleKind::Major),
+            Quantization::Minor => scale(ScaleKind::Minor),
+            Quantization::Pentatonic => scale(ScaleKind::Pentatonic),
+            Quantization::Harmonics => Quantize::Harmonics(root),
+            Quantization::Tuning => self.tuning_scale.map_or(Quantize::Off, Quantize::Scale),
+        });
+        reconstructor.set_quantize_amount(self.params.quantize_amount.value());
         reconstructor.set_detune(self.params.detune.value());
+        reconstructor.set_drift_rate(self.params.drift_rate.value());
+        reconstructor.set_noise_level(self.params.noise_level.value());
+        reconstructor.set_transient_mix(self.params.transient_mix.value());
+        reconstructor.set_mix(self.params.mix.value());
+        reconstructor.set_output_gain(self.params.output_gain.value());
+        reconstructor.set_limiter_mode(match self.params.limiter_mode.value() {
+            Limit::HardClip => LimiterMode::HardClip,
+            Limit::SoftClip => LimiterMode::SoftClip,
+            Limit::Lookahead => LimiterMode::Lookahead,
+        });
         reconstructor.set_synth_mode(self.params.synth_mode.value());
-        reconstructor.run(
-            &self.input[0..buffer.samples()],
-            &mut self.output[0..buffer.samples()],
-            self.events.as_slice(),
+        reconstructor.set_reference_note(self.params.reference_note.value() as u8);
+        let slot = |param: &IntParam| param.value() as usize - 1;
+        reconstructor.set_snapshot_source(match self.params.snapshot_mode.value() {
+            SnapshotMode::Live => SnapshotSource::Live,
+            SnapshotMode::Recall => SnapshotSource::Recall(slot(&self.params.snapshot_slot)),
+            SnapshotMode::Morph => SnapshotSource::Morph {
+                from: slot(&self.params.morph_from),
+                to: slot(&self.params.morph_to),
+                amount: self.params.morph.value(),
+            },
+        });
+        reconstructor.set_panning(
+            match self.params.pan_mode.value() {
+                Pan::Index => PanMode::Index,
+                Pan::Frequency => PanMode::Frequency,
+                Pan::Random => PanMode::Random,
+                Pan::Alternating => PanMode::Alternating,
+            },
+            self.params.width.value(),
         );
+        let [left, right] = &mut self.output;
+        let cross_mode = match self.params.cross_mode.value() {
+            Cross::Off => CrossMode::Off,
+            Cross::Amplitudes => CrossMode::Amplitudes,
+            Cross::Envelope => CrossMode::Envelope,
+        };
+        reconstructor.set_cross_mode(cross_mode);
+        reconstructor.set_cross_swap(self.params.cross_swap.value());
+        let [left_input, right_input] = &self.input;
+        let input = [&left_input[0..buffer.samples()], &right_input[0..buffer.samples()]];
+        let output = [&mut left[0..buffer.samples()], &mut right[0..buffer.samples()]];
+        if cross_mode == CrossMode::Off {
+            reconstructor.run(input, output, self.events.as_slice());
+        } else {
+            reconstructor.run_with_sidechain(
+                input,
+                &self.sidechain[0..buffer.samples()],
+                output,
+                self.events.as_slice(),
+            );
+        }
+
+        let capture = self.params.capture.value();
+        if capture && !self.capture_held {
+            reconstructor.capture_snapshot(slot(&self.params.snapshot_slot));
+            self.snapshots_dirty = true;
+        }
+        self.capture_held = capture;
+        if self.snapshots_dirty {
+            // Never block the audio thread, try again next block instead
+            if let Ok(mut snapshots) = self.params.snapshots.try_write() {
+                snapshots.copy_snapshots_from(reconstructor.snapshot_bank());
+                self.snapshots_dirty = false;
+            }
+        }
 
         self.reconstructor = Some(reconstructor);
 
-        let output_channel = buffer.as_slice().get_mut(0).unwrap();
-        for (out, out_copy) in output_channel.iter_mut().zip(self.output.iter()) {
-            *out = *out_copy;
+        match buffer.as_slice() {
+            [left, right, ..] => {
+                left.copy_from_slice(&self.output[0][0..left.len()]);
+                right.copy_from_slice(&self.output[1][0..right.len()]);
+            }
+            [mono] => {
+                // Averaged so that centred sources, like the dry path, keep
+                // their level and stay under the limiter's ceiling
+                for ((out, left), right) in mono
+                    .iter_mut()
+                    .zip(self.output[0].iter())
+                    .zip(self.output[1].iter())
+                {
+                    *out = (*left + *right) * 0.5;
+                }
+            }
+            [] => (),
         }
 
         ProcessStatus::Normal
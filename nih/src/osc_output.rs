@@ -0,0 +1,56 @@
+use core::osc_sender::{OscSender, PeakQueue};
+use core::reconstructor::Reconstructor;
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+/// Sends the tracked peaks over OSC once a target is set. Senders are started
+/// and stopped off the audio thread, their queues are handed to the
+/// reconstructor through `pending`.
+#[derive(Default)]
+pub struct OscOutput {
+    sender: Mutex<Option<OscSender>>,
+    // The queue for the audio thread to pick up, and afterwards the one it
+    // replaced so that it is freed here instead
+    pending: Mutex<Option<PeakQueue>>,
+    changed: AtomicBool,
+}
+
+impl OscOutput {
+    fn hand_over(&self, queue: Option<PeakQueue>) {
+        if let Ok(mut pending) = self.pending.lock() {
+            *pending = queue;
+            self.changed.store(true, Ordering::Release);
+        }
+    }
+
+    /// Starts sending to `target`, a host and port like "127.0.0.1:9000".
+    pub fn start(&self, target: &str) -> io::Result<()> {
+        let sender = OscSender::spawn(target)?;
+        self.hand_over(Some(sender.queue()));
+        if let Ok(mut current) = self.sender.lock() {
+            *current = Some(sender);
+        }
+        Ok(())
+    }
+
+    pub fn stop(&self) {
+        self.hand_over(None);
+        if let Ok(mut current) = self.sender.lock() {
+            *current = None;
+        }
+    }
+
+    /// Gives the reconstructor the newest queue. Never blocks, so it can be
+    /// called from the audio thread.
+    pub fn update(&self, reconstructor: &mut Reconstructor) {
+        if !self.changed.load(Ordering::Acquire) {
+            return;
+        }
+        if let Ok(mut pending) = self.pending.try_lock() {
+            let replaced = reconstructor.set_peak_queue(pending.take());
+            *pending = replaced;
+            self.changed.store(false, Ordering::Release);
+        }
+    }
+}
@@ -0,0 +1,38 @@
+use core::osc::Wavetable;
+use core::reconstructor::Reconstructor;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Hands wavetables loaded by the editor to the audio thread. Tables are
+/// built off the audio thread and the ones they replace are freed there too.
+#[derive(Default)]
+pub struct WavetableInput {
+    // The table for the audio thread to pick up, and afterwards the one it
+    // replaced
+    pending: Mutex<Option<Arc<Wavetable>>>,
+    changed: AtomicBool,
+}
+
+impl WavetableInput {
+    /// Builds the table for a single cycle, empty for none.
+    pub fn load(&self, cycle: &[f32]) {
+        let table = Wavetable::new(cycle).map(Arc::new);
+        if let Ok(mut pending) = self.pending.lock() {
+            *pending = table;
+            self.changed.store(true, Ordering::Release);
+        }
+    }
+
+    /// Gives the reconstructor the newest table. Never blocks, so it can be
+    /// called from the audio thread.
+    pub fn update(&self, reconstructor: &mut Reconstructor) {
+        if !self.changed.load(Ordering::Acquire) {
+            return;
+        }
+        if let Ok(mut pending) = self.pending.try_lock() {
+            let replaced = reconstructor.swap_wavetable(pending.take());
+            *pending = replaced;
+            self.changed.store(false, Ordering::Release);
+        }
+    }
+}